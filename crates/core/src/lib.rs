@@ -1,5 +1,5 @@
 use anyhow::Result;
-use std::collections::{btree_map::Entry, BTreeMap, HashMap};
+use std::collections::{btree_map::Entry, BTreeMap, HashMap, HashSet};
 use std::fmt::{self, Write};
 use std::ops::Deref;
 use wit_parser::*;
@@ -12,6 +12,7 @@ pub use ns::Ns;
 #[derive(Default)]
 pub struct Types {
     type_info: HashMap<TypeId, TypeInfo>,
+    opaque: HashSet<Type>,
 }
 
 #[derive(Default, Clone, Copy, Debug)]
@@ -42,6 +43,10 @@ pub struct TypeInfo {
 
     /// Whether or not this type (transitively) has a borrow handle.
     pub has_borrow_handle: bool,
+
+    /// Whether or not this type (transitively) has a type marked with
+    /// `Types::opaque`.
+    pub has_opaque: bool,
 }
 
 impl std::ops::BitOrAssign for TypeInfo {
@@ -52,10 +57,22 @@ impl std::ops::BitOrAssign for TypeInfo {
         self.has_list |= rhs.has_list;
         self.has_resource |= rhs.has_resource;
         self.has_borrow_handle |= rhs.has_borrow_handle;
+        self.has_opaque |= rhs.has_opaque;
     }
 }
 
 impl Types {
+    /// Marks `ty` as opaque, for generators which substitute a type of their
+    /// own for it. Types containing it don't inherit whether it has lists or
+    /// resources, and are flagged with `has_opaque` instead.
+    ///
+    /// This must be called before `analyze`. Named types used by an opaque
+    /// type which is itself used are always considered owned, as that's the
+    /// representation it's converted from and to.
+    pub fn opaque(&mut self, ty: Type) {
+        self.opaque.insert(ty);
+    }
+
     pub fn analyze(&mut self, resolve: &Resolve) {
         for (t, _) in resolve.types.iter() {
             self.type_id_info(resolve, t);
@@ -80,6 +97,20 @@ impl Types {
                 }
             }
         }
+        for ty in self.opaque.clone() {
+            let Type::Id(id) = ty else { continue };
+            let info = self.type_info[&id];
+            if !info.owned && !info.borrowed {
+                continue;
+            }
+            let mut live = LiveTypes::default();
+            live.add_type_id(resolve, id);
+            for id in live.iter() {
+                if resolve.types[id].name.is_some() {
+                    self.type_info.get_mut(&id).unwrap().owned = true;
+                }
+            }
+        }
     }
 
     fn type_info_func(&mut self, resolve: &Resolve, func: &Function, import: bool) {
@@ -204,6 +235,15 @@ impl Types {
     }
 
     pub fn type_info(&mut self, resolve: &Resolve, ty: &Type) -> TypeInfo {
+        if self.opaque.contains(ty) {
+            if let Type::Id(id) = ty {
+                self.type_id_info(resolve, *id);
+            }
+            return TypeInfo {
+                has_opaque: true,
+                ..TypeInfo::default()
+            };
+        }
         let mut info = TypeInfo::default();
         match ty {
             Type::String => info.has_list = true,
@@ -2,6 +2,9 @@
 
 extern crate alloc;
 
+#[cfg(feature = "std")]
+extern crate std;
+
 #[cfg(feature = "macros")]
 pub use wit_bindgen_rust_macro::*;
 
@@ -9,6 +12,11 @@ pub use wit_bindgen_rust_macro::*;
 #[doc(hidden)]
 pub use bitflags;
 
+// Re-export `serde` so that generated types can derive its traits.
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub use serde;
+
 #[doc(hidden)]
 pub mod rt {
 
@@ -53,6 +61,26 @@ pub mod rt {
         old_len: usize,
         align: usize,
         new_len: usize,
+    ) -> *mut u8 {
+        #[cfg(feature = "realloc-stats")]
+        realloc::record(old_len, new_len);
+        let realloc = realloc::REALLOC.load(core::sync::atomic::Ordering::Acquire);
+        if realloc.is_null() {
+            return default_realloc(old_ptr, old_len, align, new_len);
+        }
+        // Only `set_realloc` stores to `REALLOC`, from a `Realloc`.
+        let realloc = core::mem::transmute::<*mut (), realloc::Realloc>(realloc);
+        realloc(old_ptr, old_len, align, new_len)
+    }
+
+    /// The `cabi_realloc` of the global allocator, used unless another one is
+    /// set with [`realloc::set_realloc`].
+    #[cfg(feature = "realloc")]
+    pub unsafe fn default_realloc(
+        old_ptr: *mut u8,
+        old_len: usize,
+        align: usize,
+        new_len: usize,
     ) -> *mut u8 {
         let layout;
         let ptr = if old_len == 0 {
@@ -82,6 +110,143 @@ pub mod rt {
         return ptr;
     }
 
+    /// The allocations the host makes through `cabi_realloc` to pass lists and
+    /// strings to the component.
+    pub mod realloc {
+        /// The signature of `cabi_realloc`.
+        pub type Realloc =
+            unsafe fn(old_ptr: *mut u8, old_len: usize, align: usize, new_len: usize) -> *mut u8;
+
+        // The `Realloc`, if any, as a pointer to be stored atomically.
+        #[cfg(feature = "realloc")]
+        pub(super) static REALLOC: core::sync::atomic::AtomicPtr<()> =
+            core::sync::atomic::AtomicPtr::new(core::ptr::null_mut());
+
+        /// Routes `cabi_realloc` to `realloc`, for the host's allocations of
+        /// the arguments of exports and the results of imports.
+        ///
+        /// Only allocation goes through `realloc`: bindings free this memory
+        /// with the global allocator, either by dropping the `Vec`s and
+        /// `String`s it's handed over as or once a call no longer needs it. So
+        /// this can't route these allocations to an arena or any other
+        /// allocator of its own, and is meant for wrapping the global
+        /// allocator, such as to trace or limit allocations.
+        ///
+        /// Unlike `instrument::set_hook` and `panic::set_reporter`, this is
+        /// unsafe as bindings trust the memory `realloc` returns.
+        ///
+        /// # Safety
+        ///
+        /// `realloc` must behave as `cabi_realloc`: with an `old_len` of 0, it
+        /// returns `new_len` bytes aligned to `align`, and otherwise it grows
+        /// the allocation of `old_len` bytes at `old_ptr` which it returned,
+        /// preserving its contents.
+        ///
+        /// The memory must be allocated by the global allocator, with the
+        /// layout of `new_len` bytes aligned to `align`, as that's how it's
+        /// deallocated. Delegating to [`default_realloc`](super::default_realloc)
+        /// does so.
+        #[cfg(feature = "realloc")]
+        pub unsafe fn set_realloc(realloc: Realloc) {
+            REALLOC.store(realloc as *mut (), core::sync::atomic::Ordering::Release);
+        }
+
+        /// Allocations made by the host, for one kind of value.
+        #[cfg(feature = "realloc-stats")]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct Counts {
+            /// The number of calls allocating or growing an allocation.
+            pub allocations: u64,
+            /// The number of bytes allocated.
+            pub bytes: u64,
+        }
+
+        /// Allocations made by the host since the last [`reset_stats`].
+        #[cfg(feature = "realloc-stats")]
+        #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+        pub struct Stats {
+            /// Allocations for the arguments of exports.
+            pub arguments: Counts,
+            /// Allocations for the results of imports.
+            pub results: Counts,
+        }
+
+        #[cfg(feature = "realloc-stats")]
+        mod stats {
+            use core::sync::atomic::{AtomicBool, AtomicU64};
+
+            pub static IN_IMPORT: AtomicBool = AtomicBool::new(false);
+            pub static ARGUMENTS: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+            pub static RESULTS: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+        }
+
+        #[cfg(feature = "realloc-stats")]
+        pub(super) fn record(old_len: usize, new_len: usize) {
+            use core::sync::atomic::Ordering::Relaxed;
+            if new_len <= old_len {
+                return;
+            }
+            let counts = if stats::IN_IMPORT.load(Relaxed) {
+                &stats::RESULTS
+            } else {
+                &stats::ARGUMENTS
+            };
+            counts[0].fetch_add(1, Relaxed);
+            counts[1].fetch_add((new_len - old_len) as u64, Relaxed);
+        }
+
+        /// Returns the allocations made by the host since the last
+        /// [`reset_stats`].
+        #[cfg(feature = "realloc-stats")]
+        pub fn stats() -> Stats {
+            use core::sync::atomic::Ordering::Relaxed;
+            let counts = |c: &[core::sync::atomic::AtomicU64; 2]| Counts {
+                allocations: c[0].load(Relaxed),
+                bytes: c[1].load(Relaxed),
+            };
+            Stats {
+                arguments: counts(&stats::ARGUMENTS),
+                results: counts(&stats::RESULTS),
+            }
+        }
+
+        /// Resets the counts returned by [`stats`] to zero.
+        #[cfg(feature = "realloc-stats")]
+        pub fn reset_stats() {
+            use core::sync::atomic::Ordering::Relaxed;
+            for count in stats::ARGUMENTS.iter().chain(&stats::RESULTS) {
+                count.store(0, Relaxed);
+            }
+        }
+
+        /// Marks allocations until the returned guard is dropped as being for
+        /// the results of an import, called by bindings around imports which
+        /// return lists or strings.
+        #[doc(hidden)]
+        #[inline]
+        pub fn import_results() -> ImportResults {
+            #[cfg(feature = "realloc-stats")]
+            return ImportResults {
+                outer: stats::IN_IMPORT.swap(true, core::sync::atomic::Ordering::Relaxed),
+            };
+            #[cfg(not(feature = "realloc-stats"))]
+            return ImportResults {};
+        }
+
+        #[doc(hidden)]
+        pub struct ImportResults {
+            #[cfg(feature = "realloc-stats")]
+            outer: bool,
+        }
+
+        #[cfg(feature = "realloc-stats")]
+        impl Drop for ImportResults {
+            fn drop(&mut self) {
+                stats::IN_IMPORT.store(self.outer, core::sync::atomic::Ordering::Relaxed);
+            }
+        }
+    }
+
     pub unsafe fn dealloc(ptr: i32, size: usize, align: usize) {
         if size == 0 {
             return;
@@ -90,6 +255,941 @@ pub mod rt {
         alloc::dealloc(ptr as *mut u8, layout);
     }
 
+    /// Transcoding between Rust strings and the UTF-16 and latin1+utf16
+    /// (compact UTF-16) canonical ABI string encodings.
+    ///
+    /// All buffers are allocated with an alignment of 2 and exactly the size of
+    /// the encoded string, matching allocations made through `cabi_realloc`.
+    pub mod strings {
+        use super::alloc;
+        use super::string::String;
+        use super::vec::Vec;
+        use super::Layout;
+
+        /// Set in the length of a latin1+utf16 string if it is encoded as
+        /// UTF-16 rather than latin1.
+        pub const UTF16_TAG: i32 = i32::MIN;
+
+        /// Returns the layout of a UTF-16 string of `len` code units.
+        pub fn utf16_layout(len: i32) -> Layout {
+            Layout::from_size_align(len as usize * 2, 2).unwrap()
+        }
+
+        /// Returns the layout of a latin1+utf16 string with the tagged length
+        /// `len`.
+        pub fn compact_utf16_layout(len: i32) -> Layout {
+            if len & UTF16_TAG != 0 {
+                utf16_layout(len & !UTF16_TAG)
+            } else {
+                Layout::from_size_align(len as usize, 2).unwrap()
+            }
+        }
+
+        unsafe fn alloc(layout: Layout) -> *mut u8 {
+            if layout.size() == 0 {
+                return layout.align() as *mut u8;
+            }
+            let ptr = alloc::alloc(layout);
+            if ptr.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(ptr: i32, layout: Layout) {
+            if layout.size() != 0 {
+                alloc::dealloc(ptr as *mut u8, layout);
+            }
+        }
+
+        /// Encodes `s` as UTF-16 in a new allocation, returning its pointer
+        /// and length in code units.
+        pub fn lower_utf16(s: &str) -> (i32, i32) {
+            let len = s.encode_utf16().count() as i32;
+            unsafe {
+                let ptr = alloc(utf16_layout(len)) as *mut u16;
+                for (i, unit) in s.encode_utf16().enumerate() {
+                    ptr.add(i).write(unit);
+                }
+                (ptr as i32, len)
+            }
+        }
+
+        /// Encodes `s` as latin1 if possible, or UTF-16 otherwise, in a new
+        /// allocation, returning its pointer and tagged length.
+        pub fn lower_compact_utf16(s: &str) -> (i32, i32) {
+            if !s.chars().all(|c| (c as u32) < 0x100) {
+                let (ptr, len) = lower_utf16(s);
+                return (ptr, len | UTF16_TAG);
+            }
+            let len = s.chars().count() as i32;
+            unsafe {
+                let ptr = alloc(compact_utf16_layout(len));
+                for (i, c) in s.chars().enumerate() {
+                    ptr.add(i).write(c as u8);
+                }
+                (ptr as i32, len)
+            }
+        }
+
+        /// Decodes and deallocates a UTF-16 string of `len` code units.
+        pub unsafe fn lift_utf16(ptr: i32, len: i32) -> String {
+            let units = core::slice::from_raw_parts(ptr as *const u16, len as usize);
+            let s = if cfg!(debug_assertions) {
+                String::from_utf16(units).unwrap()
+            } else {
+                String::from_utf16_lossy(units)
+            };
+            dealloc(ptr, utf16_layout(len));
+            s
+        }
+
+        /// Decodes and deallocates a latin1+utf16 string with the tagged
+        /// length `len`.
+        pub unsafe fn lift_compact_utf16(ptr: i32, len: i32) -> String {
+            if len & UTF16_TAG != 0 {
+                return lift_utf16(ptr, len & !UTF16_TAG);
+            }
+            let bytes = core::slice::from_raw_parts(ptr as *const u8, len as usize);
+            let s = bytes.iter().map(|b| *b as char).collect();
+            dealloc(ptr, compact_utf16_layout(len));
+            s
+        }
+
+        /// Takes ownership of a latin1+utf16 string with the tagged length
+        /// `len` as UTF-16 code units, widening it if it is latin1.
+        pub unsafe fn lift_compact_utf16_raw(ptr: i32, len: i32) -> Vec<u16> {
+            if len & UTF16_TAG != 0 {
+                let len = (len & !UTF16_TAG) as usize;
+                return Vec::from_raw_parts(ptr as *mut u16, len, len);
+            }
+            let bytes = core::slice::from_raw_parts(ptr as *const u8, len as usize);
+            let units = bytes.iter().map(|b| u16::from(*b)).collect();
+            dealloc(ptr, compact_utf16_layout(len));
+            units
+        }
+
+        /// Deallocates a latin1+utf16 string with the tagged length `len`.
+        pub unsafe fn dealloc_compact_utf16(ptr: i32, len: i32) {
+            dealloc(ptr, compact_utf16_layout(len));
+        }
+    }
+
+    /// Lifecycle tracking of resource handles used by generated bindings.
+    ///
+    /// With the `handle-tracking` feature enabled every own and borrow handle
+    /// created or consumed by generated resource glue is recorded in a
+    /// guest-side table, and an operation on a handle which isn't live (such
+    /// as a use after drop or a double drop), or the creation of a handle
+    /// which already is, panics with the resource and operation involved.
+    /// Without the feature these functions do nothing.
+    pub mod handles {
+        /// The kind of value being tracked.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+        pub enum Kind {
+            /// An owned handle.
+            Own,
+            /// A borrowed handle, of which several may be live at once.
+            Borrow,
+            /// The representation of an exported resource, which is live from
+            /// its creation until its destructor runs. Borrows of it passed to
+            /// exports and dereferences of its own handles are checked against
+            /// this, but aren't tracked themselves.
+            Rep,
+        }
+
+        impl Kind {
+            /// Returns the kind of an imported resource's handle.
+            pub fn from_owned(owned: bool) -> Kind {
+                if owned {
+                    Kind::Own
+                } else {
+                    Kind::Borrow
+                }
+            }
+        }
+
+        /// Records that `handle` of `resource` became live through `op`.
+        #[inline]
+        pub fn acquire(resource: &'static str, kind: Kind, handle: i32, op: &'static str) {
+            #[cfg(feature = "handle-tracking")]
+            table::acquire(resource, kind, handle, op);
+            #[cfg(not(feature = "handle-tracking"))]
+            let _ = (resource, kind, handle, op);
+        }
+
+        /// Records that `handle` of `resource` was consumed by `op`.
+        #[inline]
+        pub fn release(resource: &'static str, kind: Kind, handle: i32, op: &'static str) {
+            #[cfg(feature = "handle-tracking")]
+            table::release(resource, kind, handle, op);
+            #[cfg(not(feature = "handle-tracking"))]
+            let _ = (resource, kind, handle, op);
+        }
+
+        /// Checks that `handle` of `resource` is live for a use by `op`
+        /// which doesn't acquire or release it.
+        #[inline]
+        pub fn check(resource: &'static str, kind: Kind, handle: i32, op: &'static str) {
+            #[cfg(feature = "handle-tracking")]
+            table::check(resource, kind, handle, op);
+            #[cfg(not(feature = "handle-tracking"))]
+            let _ = (resource, kind, handle, op);
+        }
+
+        #[cfg(feature = "handle-tracking")]
+        mod table {
+            use super::Kind;
+            use crate::alloc::collections::BTreeMap;
+            use core::cell::UnsafeCell;
+            use core::sync::atomic::{AtomicBool, Ordering};
+
+            type Table = BTreeMap<(&'static str, Kind, i32), usize>;
+
+            /// The table behind a spin lock, since bindings generated with the
+            /// `threads` option may be called from threads sharing memory.
+            struct Locked {
+                locked: AtomicBool,
+                table: UnsafeCell<Option<Table>>,
+            }
+
+            unsafe impl Sync for Locked {}
+
+            static TABLE: Locked = Locked {
+                locked: AtomicBool::new(false),
+                table: UnsafeCell::new(None),
+            };
+
+            /// Runs `f` on the table while holding the lock. `f` mustn't
+            /// panic, so callers panic once it returns and the lock is free.
+            fn with_table<R>(f: impl FnOnce(&mut Table) -> R) -> R {
+                while TABLE
+                    .locked
+                    .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+                {
+                    core::hint::spin_loop();
+                }
+                let result = f(unsafe { (*TABLE.table.get()).get_or_insert_with(Table::new) });
+                TABLE.locked.store(false, Ordering::Release);
+                result
+            }
+
+            pub fn acquire(resource: &'static str, kind: Kind, handle: i32, op: &'static str) {
+                let was_live = with_table(|table| {
+                    let live = table.entry((resource, kind, handle)).or_insert(0);
+                    if *live > 0 && kind != Kind::Borrow {
+                        return true;
+                    }
+                    *live += 1;
+                    false
+                });
+                if was_live {
+                    panic!(
+                        "`{op}` of `{resource}` {kind:?} handle {handle}: handle is already live"
+                    );
+                }
+            }
+
+            pub fn release(resource: &'static str, kind: Kind, handle: i32, op: &'static str) {
+                let was_live = with_table(|table| match table.get_mut(&(resource, kind, handle)) {
+                    Some(live) if *live > 1 => {
+                        *live -= 1;
+                        true
+                    }
+                    Some(_) => {
+                        table.remove(&(resource, kind, handle));
+                        true
+                    }
+                    None => false,
+                });
+                if !was_live {
+                    not_live(resource, kind, handle, op);
+                }
+            }
+
+            pub fn check(resource: &'static str, kind: Kind, handle: i32, op: &'static str) {
+                if !with_table(|table| table.contains_key(&(resource, kind, handle))) {
+                    not_live(resource, kind, handle, op);
+                }
+            }
+
+            fn not_live(resource: &'static str, kind: Kind, handle: i32, op: &'static str) -> ! {
+                panic!(
+                    "`{op}` of `{resource}` {kind:?} handle {handle}: \
+                     handle is not live (used after drop or dropped twice?)"
+                )
+            }
+        }
+    }
+
+    /// Descriptions of a world which generated bindings can embed as
+    /// constants, so that a component can inspect what it imports and exports
+    /// without shipping its WIT.
+    pub mod reflect {
+        /// A world along with everything it imports and exports.
+        #[derive(Clone, Copy, Debug)]
+        pub struct World {
+            pub name: &'static str,
+            pub docs: Option<&'static str>,
+            pub imports: &'static [Item],
+            pub exports: &'static [Item],
+        }
+
+        /// An import or export of a world.
+        #[derive(Clone, Copy, Debug)]
+        pub enum Item {
+            Interface(Interface),
+            Function(Function),
+            Resource(Resource),
+        }
+
+        /// An interface, named either by its package-qualified name such as
+        /// `my:pkg/iface` or, if declared inline in a world, its plain name.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Interface {
+            pub name: &'static str,
+            pub docs: Option<&'static str>,
+            pub functions: &'static [Function],
+            pub resources: &'static [Resource],
+        }
+
+        /// A function, named as in the canonical ABI, e.g. `[method]r.foo`
+        /// for methods of the resource `r`.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Function {
+            pub name: &'static str,
+            pub docs: Option<&'static str>,
+            pub params: &'static [Value],
+            pub results: &'static [Value],
+        }
+
+        /// A resource type.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Resource {
+            pub name: &'static str,
+            pub docs: Option<&'static str>,
+        }
+
+        /// A parameter or result of a function, with its type written as in
+        /// WIT, e.g. `list<u8>`. Results are only named if all results of the
+        /// function are.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Value {
+            pub name: Option<&'static str>,
+            pub ty: &'static str,
+        }
+
+        impl World {
+            /// Returns the imported interface named `name`, if any.
+            pub fn import(&self, name: &str) -> Option<&'static Interface> {
+                find_interface(self.imports, name)
+            }
+
+            /// Returns the exported interface named `name`, if any.
+            pub fn export(&self, name: &str) -> Option<&'static Interface> {
+                find_interface(self.exports, name)
+            }
+        }
+
+        impl Interface {
+            /// Returns the function named `name`, if any.
+            pub fn function(&self, name: &str) -> Option<&'static Function> {
+                self.functions.iter().find(|f| f.name == name)
+            }
+        }
+
+        fn find_interface(items: &'static [Item], name: &str) -> Option<&'static Interface> {
+            items.iter().find_map(|item| match item {
+                Item::Interface(i) if i.name == name => Some(i),
+                _ => None,
+            })
+        }
+    }
+
+    /// Hooks observing every call made through generated bindings with the
+    /// `instrument` option, for example to log calls with `tracing` or `log`.
+    pub mod instrument {
+        use super::boxed::Box;
+        use core::ptr;
+        use core::sync::atomic::{AtomicPtr, Ordering};
+
+        /// Whether a call is to an import or from the host to an export.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum Direction {
+            Import,
+            Export,
+        }
+
+        /// A call of an import or export.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub struct Call {
+            /// The name of the interface of the function, or `None` for
+            /// functions of the world itself.
+            pub interface: Option<&'static str>,
+            /// The name of the function, as in the canonical ABI.
+            pub function: &'static str,
+            pub direction: Direction,
+        }
+
+        /// Observes calls.
+        pub trait Hook: Sync {
+            /// Called before `call`, returning a value passed to `exit`, such
+            /// as the time of the call.
+            fn enter(&self, call: &Call) -> u64;
+
+            /// Called once `call` returns, or unwinds.
+            fn exit(&self, call: &Call, entered: u64);
+        }
+
+        // A leaked `&'static dyn Hook`, as the reference itself is too wide
+        // to be stored atomically.
+        static HOOK: AtomicPtr<&'static dyn Hook> = AtomicPtr::new(ptr::null_mut());
+
+        /// Sets the hook observing calls, replacing any previous one.
+        ///
+        /// Calls already in progress on other threads may still notify the
+        /// previous hook of their end.
+        pub fn set_hook(hook: &'static dyn Hook) {
+            let hook = Box::into_raw(Box::new(hook));
+            // The previous hook may still be read by other threads, so it's
+            // leaked rather than freed.
+            HOOK.store(hook, Ordering::Release);
+        }
+
+        /// Notifies the hook, if any, of the start of `call`, returning a
+        /// guard which notifies it of the end of the call when dropped.
+        #[inline]
+        pub fn enter(call: Call) -> Guard {
+            // `HOOK` is null or a leaked box, which is never freed.
+            let hook = unsafe { HOOK.load(Ordering::Acquire).as_ref().copied() };
+            let entered = hook.map(|hook| (hook, hook.enter(&call)));
+            Guard { call, entered }
+        }
+
+        /// The guard returned by [`enter`].
+        #[must_use]
+        pub struct Guard {
+            call: Call,
+            entered: Option<(&'static dyn Hook, u64)>,
+        }
+
+        impl Drop for Guard {
+            fn drop(&mut self) {
+                if let Some((hook, entered)) = self.entered {
+                    hook.exit(&self.call, entered);
+                }
+            }
+        }
+    }
+
+    /// The instances of types implementing exports with the `singleton`
+    /// option.
+    pub mod singleton {
+        use core::cell::UnsafeCell;
+        use core::ops::{Deref, DerefMut};
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        /// The instance of `T`, created with `Default` when first borrowed.
+        pub struct Instance<T> {
+            borrowed: AtomicBool,
+            value: UnsafeCell<Option<T>>,
+        }
+
+        // The value is only accessed while `borrowed` is held, by one thread
+        // at a time.
+        unsafe impl<T: Send> Sync for Instance<T> {}
+
+        impl<T> Instance<T> {
+            pub const fn new() -> Instance<T> {
+                Instance {
+                    borrowed: AtomicBool::new(false),
+                    value: UnsafeCell::new(None),
+                }
+            }
+        }
+
+        impl<T> Default for Instance<T> {
+            fn default() -> Instance<T> {
+                Instance::new()
+            }
+        }
+
+        impl<T: Default> Instance<T> {
+            /// Borrows the instance for the duration of an export call.
+            ///
+            /// Panics if it's already borrowed, as when an export is called
+            /// while another one is running.
+            pub fn borrow_mut(&'static self) -> InstanceMut<T> {
+                if self.borrowed.swap(true, Ordering::Acquire) {
+                    panic!("the export instance is already in use by another export call");
+                }
+                let instance = InstanceMut(self);
+                // The value is created once `instance` holds the borrow, so that
+                // it is released if `default` panics.
+                unsafe { (*self.value.get()).get_or_insert_with(T::default) };
+                instance
+            }
+        }
+
+        /// A borrow of an [`Instance`], released when dropped.
+        pub struct InstanceMut<T: 'static>(&'static Instance<T>);
+
+        impl<T> Deref for InstanceMut<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                unsafe { (*self.0.value.get()).as_ref().unwrap() }
+            }
+        }
+
+        impl<T> DerefMut for InstanceMut<T> {
+            fn deref_mut(&mut self) -> &mut T {
+                unsafe { (*self.0.value.get()).as_mut().unwrap() }
+            }
+        }
+
+        impl<T> Drop for InstanceMut<T> {
+            fn drop(&mut self) {
+                self.0.borrowed.store(false, Ordering::Release);
+            }
+        }
+    }
+
+    /// Runs the futures of exports made `async` by the `async_exports` option.
+    pub mod executor {
+        use alloc::sync::Arc;
+        use alloc::task::Wake;
+        use core::future::Future;
+        use core::pin::pin;
+        use core::sync::atomic::{AtomicBool, Ordering};
+        use core::task::{Context, Poll, Waker};
+
+        /// Whether the future being run was woken since it was last polled.
+        struct Woken(AtomicBool);
+
+        impl Wake for Woken {
+            fn wake(self: Arc<Self>) {
+                self.wake_by_ref();
+            }
+
+            fn wake_by_ref(self: &Arc<Self>) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+
+        /// Polls `future` until it completes, returning its output.
+        ///
+        /// A component has no event loop on which a future could wait for
+        /// the host, so `future` is polled again only once it's been woken,
+        /// by itself or by a future it awaits, such as when yielding.
+        ///
+        /// Panics if `future` is pending without having been woken, as
+        /// nothing could then make it progress.
+        pub fn block_on<F: Future>(future: F) -> F::Output {
+            let mut future = pin!(future);
+            let woken = Arc::new(Woken(AtomicBool::new(false)));
+            let waker = Waker::from(woken.clone());
+            let mut cx = Context::from_waker(&waker);
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+                if !woken.0.swap(false, Ordering::Acquire) {
+                    panic!("an export's future is pending with nothing to wake it");
+                }
+            }
+        }
+    }
+
+    /// Support for bindings generated with the `case_conversions` option,
+    /// converting enums and flags from their WIT names and discriminants.
+    pub mod names {
+        use super::string::{String, ToString};
+        use core::fmt;
+
+        /// The error returned when a name or discriminant isn't one of the
+        /// cases of an enum, or flags of a flags type.
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub struct UnknownCase {
+            ty: &'static str,
+            found: String,
+        }
+
+        impl UnknownCase {
+            pub fn new(ty: &'static str, found: impl fmt::Display) -> UnknownCase {
+                UnknownCase {
+                    ty,
+                    found: found.to_string(),
+                }
+            }
+
+            /// The WIT name of the enum or flags type.
+            pub fn ty(&self) -> &'static str {
+                self.ty
+            }
+
+            /// The name or discriminant which was found.
+            pub fn found(&self) -> &str {
+                &self.found
+            }
+        }
+
+        impl fmt::Display for UnknownCase {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "`{}` isn't a case of `{}`", self.found, self.ty)
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl std::error::Error for UnknownCase {}
+    }
+
+    /// Reporting of panics through an import before trapping, as otherwise
+    /// their message is lost unless WASI stderr is available.
+    ///
+    /// The reporter is typically a logging import of the world:
+    ///
+    /// ```ignore
+    /// wit_bindgen::rt::panic::install(|message, location| {
+    ///     let location = location.map(|l| format!(" at {l}")).unwrap_or_default();
+    ///     logging::log(Level::Error, &format!("panicked{location}: {message}"));
+    /// });
+    /// ```
+    ///
+    /// Components without `std` instead pass [`handle`] their
+    /// `#[panic_handler]`.
+    #[cfg(feature = "panic-reporting")]
+    pub mod panic {
+        use core::mem;
+        use core::panic::{Location, PanicInfo};
+        use core::ptr;
+        use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+        /// Reports the message of a panic and where it happened.
+        pub type Reporter = fn(message: &str, location: Option<&Location<'_>>);
+
+        // The `Reporter`, if any, as a pointer to be stored atomically.
+        static REPORTER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+        // Set while reporting, so that a panicking reporter isn't called again.
+        static REPORTING: AtomicBool = AtomicBool::new(false);
+
+        /// Sets the function panics are reported to, replacing any previous
+        /// one.
+        ///
+        /// Only one panic is reported at a time: panics on other threads while
+        /// one is being reported, like those of the reporter itself, aren't
+        /// reported.
+        pub fn set_reporter(reporter: Reporter) {
+            REPORTER.store(reporter as *mut (), Ordering::Release);
+        }
+
+        /// Sets `reporter` and installs a panic hook calling it, replacing
+        /// the default hook which prints to stderr.
+        #[cfg(feature = "std")]
+        pub fn install(reporter: Reporter) {
+            set_reporter(reporter);
+            std::panic::set_hook(super::boxed::Box::new(|info| {
+                let payload = info.payload();
+                let message = match payload.downcast_ref::<&str>() {
+                    Some(s) => s,
+                    None => match payload.downcast_ref::<super::string::String>() {
+                        Some(s) => s.as_str(),
+                        None => "Box<dyn Any>",
+                    },
+                };
+                report(message, info.location());
+            }));
+        }
+
+        /// Reports the panic described by `info` and traps, for use as the
+        /// `#[panic_handler]` of `no_std` components.
+        pub fn handle(info: &PanicInfo<'_>) -> ! {
+            let message = info.message();
+            match message.as_str() {
+                Some(s) => report(s, info.location()),
+                None => report(&::alloc::format!("{message}"), info.location()),
+            }
+            trap()
+        }
+
+        fn report(message: &str, location: Option<&Location<'_>>) {
+            let reporter = REPORTER.load(Ordering::Acquire);
+            if !reporter.is_null() {
+                // Only `set_reporter` stores to `REPORTER`, from a `Reporter`.
+                let reporter = unsafe { mem::transmute::<*mut (), Reporter>(reporter) };
+                if !REPORTING.swap(true, Ordering::Relaxed) {
+                    reporter(message, location);
+                    REPORTING.store(false, Ordering::Relaxed);
+                }
+            }
+        }
+
+        fn trap() -> ! {
+            #[cfg(target_arch = "wasm32")]
+            core::arch::wasm32::unreachable();
+            #[cfg(all(not(target_arch = "wasm32"), feature = "std"))]
+            std::process::abort();
+            #[cfg(all(not(target_arch = "wasm32"), not(feature = "std")))]
+            loop {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    pub use dynamic::Val;
+
+    /// Dynamically typed values, used by generated bindings to call imports
+    /// and exports whose types are only known at runtime.
+    ///
+    /// Values are lifted and lowered by the same canonical ABI glue as the
+    /// statically typed bindings, using the helpers of this module to take
+    /// apart the values being lowered.
+    pub mod dynamic {
+        use super::boxed::Box;
+        use super::string::String;
+        use super::vec::Vec;
+
+        /// A value of any WIT type but resources, whose handles can't be
+        /// passed dynamically.
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum Val {
+            Bool(bool),
+            U8(u8),
+            U16(u16),
+            U32(u32),
+            U64(u64),
+            S8(i8),
+            S16(i16),
+            S32(i32),
+            S64(i64),
+            Float32(f32),
+            Float64(f64),
+            Char(char),
+            String(String),
+            List(Vec<Val>),
+            /// The fields of a record in order, along with their names.
+            Record(Vec<(String, Val)>),
+            Tuple(Vec<Val>),
+            /// The name of a variant's case along with its payload, if any.
+            Variant(String, Option<Box<Val>>),
+            /// The name of an enum's case.
+            Enum(String),
+            /// The index of a union's case along with its payload.
+            Union(u32, Box<Val>),
+            Option(Option<Box<Val>>),
+            /// A result whose payloads are `None` if that case has no type.
+            Result(Result<Option<Box<Val>>, Option<Box<Val>>>),
+            /// The names of the flags which are set.
+            Flags(Vec<String>),
+        }
+
+        /// A generated shim calling a function with dynamically typed
+        /// arguments, returning its results.
+        ///
+        /// Shims panic if given the wrong number or types of arguments.
+        pub type Shim = fn(&[Val]) -> Vec<Val>;
+
+        /// The shims of every import and export of a world, named as in
+        /// [`reflect`](super::reflect): `my:pkg/iface#func` for functions of
+        /// interfaces and `func` for functions of the world itself.
+        #[derive(Clone, Copy, Debug)]
+        pub struct Dispatch {
+            pub imports: &'static [(&'static str, Shim)],
+            pub exports: &'static [(&'static str, Shim)],
+        }
+
+        impl Dispatch {
+            /// Returns the shim of the import named `name`, if any.
+            pub fn import(&self, name: &str) -> Option<Shim> {
+                find(self.imports, name)
+            }
+
+            /// Returns the shim of the export named `name`, if any.
+            pub fn export(&self, name: &str) -> Option<Shim> {
+                find(self.exports, name)
+            }
+        }
+
+        fn find(shims: &[(&'static str, Shim)], name: &str) -> Option<Shim> {
+            shims
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, shim)| *shim)
+        }
+
+        /// An implementation of exports called with dynamically typed values,
+        /// such as a proxy forwarding every call to an import, used by
+        /// bindings generated with `dynamic_exports`.
+        pub trait Handler {
+            /// Calls the export named `func`, named as in [`Dispatch`], with
+            /// `args`, returning its results.
+            fn call(func: &str, args: Vec<Val>) -> Vec<Val>;
+        }
+
+        /// Checks that `func` was passed `len` arguments.
+        pub fn check_arity(args: &[Val], len: usize, func: &str) {
+            if args.len() != len {
+                panic!(
+                    "`{func}` takes {len} arguments but {} were given",
+                    args.len()
+                );
+            }
+        }
+
+        /// Panics as `val`, passed to or returned by `func`, isn't of the
+        /// `expected` type.
+        #[cold]
+        pub fn mismatch(func: &str, expected: &str, val: Val) -> ! {
+            panic!("`{func}` expected a value of type `{expected}`, found {val:?}")
+        }
+
+        /// Returns the `N` results of `func` returned by a [`Handler`].
+        pub fn results<const N: usize>(func: &str, results: Vec<Val>) -> [Val; N] {
+            match results.try_into() {
+                Ok(results) => results,
+                Err(results) => panic!(
+                    "`{func}` returns {N} results but {} were returned",
+                    results.len()
+                ),
+            }
+        }
+
+        /// Returns the fields of `val`, a record whose fields are `names`.
+        pub fn record<const N: usize>(
+            func: &str,
+            expected: &str,
+            val: Val,
+            names: [&str; N],
+        ) -> [Val; N] {
+            match val {
+                Val::Record(fields)
+                    if fields.len() == N
+                        && fields.iter().zip(names.iter()).all(|((a, _), b)| a == b) =>
+                {
+                    let mut fields = fields.into_iter().map(|(_, val)| val);
+                    core::array::from_fn(|_| fields.next().unwrap())
+                }
+                val => mismatch(func, expected, val),
+            }
+        }
+
+        /// Returns the elements of `val`, a tuple of `N` values.
+        pub fn tuple<const N: usize>(func: &str, expected: &str, val: Val) -> [Val; N] {
+            match val {
+                Val::Tuple(vals) if vals.len() == N => {
+                    let mut vals = vals.into_iter();
+                    core::array::from_fn(|_| vals.next().unwrap())
+                }
+                val => mismatch(func, expected, val),
+            }
+        }
+
+        /// Returns the bits of `val`, a set of the flags `names`, as the `N`
+        /// 32-bit words they are lowered to.
+        pub fn flags<const N: usize>(
+            func: &str,
+            expected: &str,
+            val: Val,
+            names: &[&str],
+        ) -> [i32; N] {
+            let set = match val {
+                Val::Flags(set) => set,
+                val => mismatch(func, expected, val),
+            };
+            let mut bits = [0; N];
+            for flag in set {
+                match names.iter().position(|name| *name == flag) {
+                    Some(i) => bits[i / 32] |= (1u32 << (i % 32)) as i32,
+                    None => mismatch(func, expected, Val::Flags(Vec::from([flag]))),
+                }
+            }
+            bits
+        }
+
+        /// Returns the names of the flags `names` set in `bits`, the 32-bit
+        /// words a set of flags is lowered to.
+        pub fn flag_names(bits: &[i32], names: &[&str]) -> Vec<String> {
+            names
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| bits[i / 32] as u32 & (1 << (i % 32)) != 0)
+                .map(|(_, name)| String::from(*name))
+                .collect()
+        }
+
+        /// Returns the index and payload of the case of `val`, a variant
+        /// whose cases are `cases` along with whether they have a payload.
+        pub fn variant(
+            func: &str,
+            expected: &str,
+            val: Val,
+            cases: &[(&str, bool)],
+        ) -> (u32, Option<Val>) {
+            match val {
+                Val::Variant(case, payload) => {
+                    match cases.iter().position(|(name, _)| *name == case) {
+                        Some(i) if cases[i].1 == payload.is_some() => {
+                            (i as u32, payload.map(|payload| *payload))
+                        }
+                        _ => mismatch(func, expected, Val::Variant(case, payload)),
+                    }
+                }
+                val => mismatch(func, expected, val),
+            }
+        }
+
+        /// Returns the index of the case of `val`, an enum whose cases are
+        /// `cases`.
+        pub fn enum_case(func: &str, expected: &str, val: Val, cases: &[&str]) -> i32 {
+            match val {
+                Val::Enum(case) => match cases.iter().position(|name| *name == case) {
+                    Some(i) => i as i32,
+                    None => mismatch(func, expected, Val::Enum(case)),
+                },
+                val => mismatch(func, expected, val),
+            }
+        }
+
+        /// Returns the index and payload of the case of `val`, a union of
+        /// `cases` cases.
+        pub fn union_case(func: &str, expected: &str, val: Val, cases: u32) -> (u32, Val) {
+            match val {
+                Val::Union(case, payload) if case < cases => (case, *payload),
+                val => mismatch(func, expected, val),
+            }
+        }
+
+        /// Returns the payload of `val`, an option.
+        pub fn option(func: &str, expected: &str, val: Val) -> Option<Val> {
+            match val {
+                Val::Option(payload) => payload.map(|payload| *payload),
+                val => mismatch(func, expected, val),
+            }
+        }
+
+        /// Returns the case and payload of `val`, a result whose cases have
+        /// payloads if `ok` and `err` are true.
+        pub fn result(
+            func: &str,
+            expected: &str,
+            val: Val,
+            ok: bool,
+            err: bool,
+        ) -> Result<Option<Val>, Option<Val>> {
+            match val {
+                Val::Result(Ok(payload)) if payload.is_some() == ok => {
+                    Ok(payload.map(|payload| *payload))
+                }
+                Val::Result(Err(payload)) if payload.is_some() == err => {
+                    Err(payload.map(|payload| *payload))
+                }
+                val => mismatch(func, expected, val),
+            }
+        }
+    }
+
     macro_rules! as_traits {
         ($(($trait_:ident $func:ident $ty:ident <=> $($tys:ident)*))*) => ($(
             pub fn $func<T: $trait_>(t: T) -> $ty {
@@ -91,6 +91,91 @@ pub trait RustGenerator<'a> {
         false
     }
 
+    /// The code unit type of raw strings, which is `u8` for UTF-8.
+    fn raw_string_unit(&self) -> &'static str {
+        "u8"
+    }
+
+    /// Return true iff a string's canonical ABI representation can be
+    /// borrowed directly as the type bindings use for borrowed strings.
+    fn can_borrow_strings(&self) -> bool {
+        true
+    }
+
+    /// Return true iff exported resources are stored in a `RefCell`, in which
+    /// case borrows of them are `&RefCell<Rep>` in bindings.
+    fn use_mutable_resources(&self) -> bool {
+        false
+    }
+
+    /// The path to `serde` if generated types implement its `Serialize` and
+    /// `Deserialize` traits using WIT names.
+    fn serde_path(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Derives the `serde` traits for a type defined with the lifetime `lt`,
+    /// returning whether they were derived.
+    ///
+    /// Types containing resources are skipped, and only owned types, those
+    /// without a lifetime, implement `Deserialize`.
+    fn print_serde_derives(&mut self, info: &TypeInfo, lt: Option<&str>) -> bool {
+        let serde = match self.serde_path() {
+            Some(serde) if !info.has_resource => serde,
+            _ => return false,
+        };
+        self.push_str(&format!("#[derive({serde}::Serialize"));
+        if lt.is_none() {
+            self.push_str(&format!(", {serde}::Deserialize"));
+        }
+        self.push_str(&format!(")]\n#[serde(crate = \"{serde}\")]\n"));
+        true
+    }
+
+    /// Return true iff records whose fields all have a natural default
+    /// implement `Default` and get a builder type.
+    fn record_builders(&self) -> bool {
+        false
+    }
+
+    /// Returns whether `ty` has a natural default value: booleans, numbers,
+    /// strings, lists, options, and records of such types.
+    fn has_natural_default(&self, ty: &Type) -> bool {
+        if self.remapped_type(ty).is_some() {
+            return false;
+        }
+        match ty {
+            Type::Char => false,
+            Type::Id(id) => match &self.resolve().types[*id].kind {
+                TypeDefKind::Option(_) | TypeDefKind::List(_) => true,
+                TypeDefKind::Record(record) => record
+                    .fields
+                    .iter()
+                    .all(|field| self.has_natural_default(&field.ty)),
+                TypeDefKind::Type(ty) => self.has_natural_default(ty),
+                _ => false,
+            },
+            _ => true,
+        }
+    }
+
+    /// The Rust type printed in place of `ty` if it's been substituted with a
+    /// type of the user's choosing.
+    fn remapped_type(&self, ty: &Type) -> Option<String> {
+        let _ = ty;
+        None
+    }
+
+    /// Returns whether `ty` is, or (transitively) contains, a type for which
+    /// `remapped_type` returns a substitute.
+    fn has_remapped_type(&self, ty: &Type) -> bool {
+        match ty {
+            _ if self.remapped_type(ty).is_some() => true,
+            Type::Id(id) => self.info(*id).has_opaque,
+            _ => false,
+        }
+    }
+
     fn is_exported_resource(&self, ty: TypeId) -> bool;
 
     fn mark_resource_owned(&mut self, resource: TypeId);
@@ -164,12 +249,55 @@ pub trait RustGenerator<'a> {
         let params = self.print_docs_and_params(func, param_mode, &sig);
         if let FunctionKind::Constructor(_) = &func.kind {
             self.push_str(" -> Self")
+        } else if let (true, Some((ok, _))) = (
+            sig.convert_errors,
+            convertible_error(self.resolve(), &func.results),
+        ) {
+            self.push_str(" -> Result<");
+            self.print_optional_ty(ok, TypeMode::Owned);
+            self.push_str(&format!(", Self::{}>", error_type_name(func)));
         } else {
             self.print_results(&func.results, TypeMode::Owned);
         }
         params
     }
 
+    /// Returns whether errors of type `err` returned with
+    /// `FnSig::convert_errors` are converted with `Display`.
+    fn displays_error(&self, err: &Type) -> bool {
+        matches!(err, Type::String) && !self.use_raw_strings()
+    }
+
+    /// Declares the associated type naming the error `func` returns with
+    /// `FnSig::convert_errors`, or with `define` defines it as the WIT error
+    /// type itself.
+    fn print_error_type(&mut self, func: &Function, define: bool) {
+        if let FunctionKind::Constructor(_) = &func.kind {
+            return;
+        }
+        let Some((_, err)) = convertible_error(self.resolve(), &func.results) else {
+            return;
+        };
+        let name = error_type_name(func);
+        if define {
+            self.push_str(&format!("type {name} = "));
+            self.print_ty(err, TypeMode::Owned);
+            self.push_str(";\n");
+            return;
+        }
+        self.push_str(&format!(
+            "/// The error returned by `{}`, converted to its WIT type by the bindings.\n",
+            func.item_name()
+        ));
+        if self.displays_error(err) {
+            self.push_str(&format!("type {name}: ::core::fmt::Display;\n"));
+        } else {
+            self.push_str(&format!("type {name}: Into<"));
+            self.print_ty(err, TypeMode::Owned);
+            self.push_str(">;\n");
+        }
+    }
+
     fn print_docs_and_params(
         &mut self,
         func: &Function,
@@ -219,7 +347,15 @@ pub trait RustGenerator<'a> {
             self.push_str(&name);
             params.push(name);
             self.push_str(": ");
-            self.print_ty(param, param_mode);
+            let mode = if sig.borrow_canonical_params
+                && is_borrowable_param(self.resolve(), param, self.can_borrow_strings())
+                && !self.has_remapped_type(param)
+            {
+                TypeMode::LeafBorrowed("'_")
+            } else {
+                param_mode
+            };
+            self.print_ty(param, mode);
             self.push_str(",");
         }
         self.push_str(")");
@@ -245,6 +381,10 @@ pub trait RustGenerator<'a> {
     }
 
     fn print_ty(&mut self, ty: &Type, mode: TypeMode) {
+        if let Some(remapped) = self.remapped_type(ty) {
+            self.push_str(&remapped);
+            return;
+        }
         match ty {
             Type::Id(t) => self.print_tyid(*t, mode),
             Type::Bool => self.push_str("bool"),
@@ -266,7 +406,7 @@ pub trait RustGenerator<'a> {
                 TypeMode::Owned | TypeMode::HandlesBorrowed(_) => {
                     if self.use_raw_strings() {
                         self.push_vec_name();
-                        self.push_str("::<u8>");
+                        self.push_str(&format!("::<{}>", self.raw_string_unit()));
                     } else {
                         self.push_string_name();
                     }
@@ -332,6 +472,13 @@ pub trait RustGenerator<'a> {
             // lifetime parameter on the type as well.
             if (info.has_list || info.has_borrow_handle) && needs_generics(self.resolve(), &ty.kind)
             {
+                // Types holding borrowed handles are always defined with a
+                // lifetime, which is elided if none is being printed. That's
+                // spelled out as `'_`, which `async fn`s require.
+                let lt = match lt {
+                    None if info.has_borrow_handle => Some("'_"),
+                    lt => lt,
+                };
                 self.print_generics(lt);
             }
 
@@ -436,17 +583,22 @@ pub trait RustGenerator<'a> {
                     }
                 }
                 if self.is_exported_resource(*ty) {
-                    self.push_str(&self.type_path_with_name(
+                    let rep = self.type_path_with_name(
                         *ty,
                         format!(
-                        "Rep{}",
-                        self.resolve().types[*ty]
-                            .name
-                            .as_deref()
-                            .unwrap()
-                            .to_upper_camel_case()
-                    ),
-                    ));
+                            "Rep{}",
+                            self.resolve().types[*ty]
+                                .name
+                                .as_deref()
+                                .unwrap()
+                                .to_upper_camel_case()
+                        ),
+                    );
+                    if self.use_mutable_resources() {
+                        self.push_str(&format!("::core::cell::RefCell<{rep}>"));
+                    } else {
+                        self.push_str(&rep);
+                    }
                 } else {
                     self.print_ty(&Type::Id(*ty), mode);
                 }
@@ -677,6 +829,8 @@ pub trait RustGenerator<'a> {
         derive_component: bool,
     ) {
         let info = self.info(id);
+        let builder =
+            self.record_builders() && !derive_component && self.has_natural_default(&Type::Id(id));
         for (name, mode) in self.modes_of(id) {
             let lt = self.lifetime_for(&info, mode);
             self.rustdoc(docs);
@@ -689,15 +843,19 @@ pub trait RustGenerator<'a> {
                 self.push_str("#[derive(wasmtime::component::Lower)]\n");
                 self.push_str("#[component(record)]\n");
             }
+            let serde = self.print_serde_derives(&info, lt);
 
             if !info.has_resource {
-                if !info.has_list {
+                if !info.has_list && !info.has_opaque {
                     self.push_str("#[repr(C)]\n");
                     self.push_str("#[derive(Copy, Clone)]\n");
                 } else {
                     self.push_str("#[derive(Clone)]\n");
                 }
             }
+            if builder {
+                self.push_str("#[derive(Default)]\n");
+            }
             self.push_str(&format!("pub struct {}", name));
             self.print_generics(lt);
             self.push_str(" {\n");
@@ -706,6 +864,9 @@ pub trait RustGenerator<'a> {
                 if derive_component {
                     self.push_str(&format!("#[component(name = \"{}\")]\n", field.name));
                 }
+                if serde {
+                    self.push_str(&format!("#[serde(rename = \"{}\")]\n", field.name));
+                }
                 self.push_str("pub ");
                 self.push_str(&to_rust_ident(&field.name));
                 self.push_str(": ");
@@ -735,6 +896,10 @@ pub trait RustGenerator<'a> {
             self.push_str("}\n");
             self.push_str("}\n");
 
+            if builder {
+                self.print_record_builder(&name, record, &info, mode, lt);
+            }
+
             if info.error {
                 self.push_str("impl");
                 self.print_generics(lt);
@@ -756,6 +921,33 @@ pub trait RustGenerator<'a> {
                 self.push_str("{}\n");
             }
         }
+
+        if !self.uses_two_names(&info) || info.has_resource {
+            return;
+        }
+        let fields = record
+            .fields
+            .iter()
+            .map(|field| (to_rust_ident(&field.name), &field.ty))
+            .collect::<Vec<_>>();
+        let to_owned = fields
+            .iter()
+            .map(|(name, ty)| format!("{name}: {},", self.owned_expr(ty, &format!("&self.{name}"))))
+            .collect::<String>();
+        let from = fields
+            .iter()
+            .map(|(name, ty)| {
+                Some(format!(
+                    "{name}: {},",
+                    self.borrowed_expr(ty, &format!("&e.{name}"))?
+                ))
+            })
+            .collect::<Option<String>>();
+        self.print_ownership_conversions(
+            id,
+            &format!("{} {{ {to_owned} }}", self.result_name(id)),
+            from.map(|from| format!("{} {{ {from} }}", self.param_name(id))),
+        );
     }
 
     fn print_typedef_tuple(&mut self, id: TypeId, tuple: &Tuple, docs: &Docs) {
@@ -774,6 +966,86 @@ pub trait RustGenerator<'a> {
         }
     }
 
+    /// Prints a builder of the record `name`, defined with the lifetime `lt`,
+    /// which starts from its default value and has a setter for each field.
+    fn print_record_builder(
+        &mut self,
+        name: &str,
+        record: &Record,
+        info: &TypeInfo,
+        mode: TypeMode,
+        lt: Option<&'static str>,
+    ) {
+        let builder = format!("{name}Builder");
+        self.push_str(&format!(
+            "/// A builder of [`{name}`], starting from its default value.\n"
+        ));
+        // Like the record, the builder can't be cloned if it holds resources.
+        if !info.has_resource {
+            self.push_str("#[derive(Clone)]\n");
+        }
+        self.push_str("#[derive(Debug, Default)]\n");
+        self.push_str(&format!("pub struct {builder}"));
+        self.print_generics(lt);
+        self.push_str(&format!(" {{\nrecord: {name}"));
+        self.print_generics(lt);
+        self.push_str(",\n}\n");
+
+        self.push_str("impl");
+        self.print_generics(lt);
+        self.push_str(&format!(" {name}"));
+        self.print_generics(lt);
+        self.push_str(" {\n");
+        self.push_str(&format!(
+            "/// Returns a builder of this record, starting from its default value.\n\
+             pub fn builder() -> {builder}"
+        ));
+        self.print_generics(lt);
+        self.push_str(" {\n::core::default::Default::default()\n}\n}\n");
+
+        self.push_str("impl");
+        self.print_generics(lt);
+        self.push_str(&format!(" {builder}"));
+        self.print_generics(lt);
+        self.push_str(" {\n");
+        for field in record.fields.iter() {
+            let field_name = to_rust_ident(&field.name);
+            // Leave `build` to finish the builder.
+            let setter = if field_name == "build" {
+                "build_".to_string()
+            } else {
+                field_name.clone()
+            };
+            let payload = match field.ty {
+                Type::Id(id) => match &self.resolve().types[dealias(self.resolve(), id)].kind {
+                    TypeDefKind::Option(payload) => Some(*payload),
+                    _ => None,
+                },
+                _ => None,
+            };
+            self.rustdoc(&field.docs);
+            self.push_str(&format!(
+                "#[must_use]\npub fn {setter}(mut self, {field_name}: "
+            ));
+            match &payload {
+                Some(payload) => self.print_ty(payload, mode),
+                None => self.print_ty(&field.ty, mode),
+            }
+            self.push_str(") -> Self {\n");
+            if payload.is_some() {
+                self.push_str(&format!("self.record.{field_name} = Some({field_name});\n"));
+            } else {
+                self.push_str(&format!("self.record.{field_name} = {field_name};\n"));
+            }
+            self.push_str("self\n}\n");
+        }
+        self.push_str(&format!(
+            "/// Returns the record built.\npub fn build(self) -> {name}"
+        ));
+        self.print_generics(lt);
+        self.push_str(" {\nself.record\n}\n}\n");
+    }
+
     fn print_typedef_variant(
         &mut self,
         id: TypeId,
@@ -848,7 +1120,8 @@ pub trait RustGenerator<'a> {
                 self.push_str("#[derive(wasmtime::component::Lower)]\n");
                 self.push_str(&format!("#[component({})]\n", derive_component));
             }
-            if !info.has_list {
+            let serde = self.print_serde_derives(&info, lt);
+            if !info.has_list && !info.has_opaque {
                 self.push_str("#[derive(Clone, Copy)]\n");
             } else {
                 self.push_str("#[derive(Clone)]\n");
@@ -856,13 +1129,20 @@ pub trait RustGenerator<'a> {
             self.push_str(&format!("pub enum {name}"));
             self.print_generics(lt);
             self.push_str("{\n");
-            for (case_name, component_name, docs, payload) in cases.clone() {
+            for (tag, (case_name, component_name, docs, payload)) in
+                cases.clone().into_iter().enumerate()
+            {
                 self.rustdoc(docs);
                 if derive_component.is_some() {
-                    if let Some(n) = component_name {
+                    if let Some(n) = &component_name {
                         self.push_str(&format!("#[component(name = \"{}\")] ", n));
                     }
                 }
+                if serde {
+                    // Union cases are tagged with their index, having no name.
+                    let tag = component_name.clone().unwrap_or_else(|| tag.to_string());
+                    self.push_str(&format!("#[serde(rename = \"{}\")] ", tag));
+                }
                 self.push_str(&case_name);
                 if let Some(ty) = payload {
                     self.push_str("(");
@@ -909,6 +1189,180 @@ pub trait RustGenerator<'a> {
                 self.push_str(" {}\n");
             }
         }
+
+        if !self.uses_two_names(&info) || info.has_resource {
+            return;
+        }
+        let owned = self.result_name(id);
+        let param = self.param_name(id);
+        let mut to_owned = String::new();
+        let mut from = Some(String::new());
+        for (case_name, _, _, payload) in cases {
+            match payload {
+                Some(ty) => {
+                    let e = self.owned_expr(ty, "e");
+                    to_owned.push_str(&format!(
+                        "{param}::{case_name}(e) => {owned}::{case_name}({e}),"
+                    ));
+                    from = from.zip(self.borrowed_expr(ty, "e")).map(|(from, e)| {
+                        format!("{from}{owned}::{case_name}(e) => {param}::{case_name}({e}),")
+                    });
+                }
+                None => {
+                    to_owned.push_str(&format!("{param}::{case_name} => {owned}::{case_name},"));
+                    from = from
+                        .map(|from| format!("{from}{owned}::{case_name} => {param}::{case_name},"));
+                }
+            }
+        }
+        self.print_ownership_conversions(
+            id,
+            &format!("match self {{ {to_owned} }}"),
+            from.map(|from| format!("match e {{ {from} }}")),
+        );
+    }
+
+    /// Generates conversions between the two versions of a type which
+    /// `uses_two_names`: a `to_owned` method on the borrowed version
+    /// evaluating `to_owned`, and, if the borrowed version can borrow from
+    /// `e`, the owned version, an `impl From<&Owned>` evaluating `from`.
+    fn print_ownership_conversions(&mut self, id: TypeId, to_owned: &str, from: Option<String>) {
+        let owned = self.result_name(id);
+        let param = self.param_name(id);
+        self.push_str(&format!(
+            "impl {param}<'_> {{
+                /// Returns an owned copy of this value.
+                pub fn to_owned(&self) -> {owned} {{
+                    {to_owned}
+                }}
+            }}
+            "
+        ));
+        if let Some(from) = from {
+            self.push_str(&format!(
+                "impl<'a> From<&'a {owned}> for {param}<'a> {{
+                    fn from(e: &'a {owned}) -> Self {{
+                        {from}
+                    }}
+                }}
+                "
+            ));
+        }
+    }
+
+    /// Returns an expression converting `e`, a reference to a borrowed value
+    /// of type `ty`, to an owned value.
+    fn owned_expr(&self, ty: &Type, e: &str) -> String {
+        let id = match ty {
+            _ if !self.type_has_list(ty) => return self.copy_expr(ty, e),
+            Type::String => return format!("(*{e}).to_owned()"),
+            Type::Id(id) => *id,
+            _ => unreachable!(),
+        };
+        let ty = &self.resolve().types[id];
+        if ty.name.is_some()
+            && matches!(
+                ty.kind,
+                TypeDefKind::Record(_) | TypeDefKind::Variant(_) | TypeDefKind::Union(_)
+            )
+        {
+            return format!("({e}).to_owned()");
+        }
+        match &ty.kind {
+            TypeDefKind::Type(ty) => self.owned_expr(ty, e),
+            TypeDefKind::List(ty) => format!(
+                "({e}).iter().map(|e| {}).collect()",
+                self.owned_expr(ty, "e")
+            ),
+            TypeDefKind::Option(ty) => {
+                format!("({e}).as_ref().map(|e| {})", self.owned_expr(ty, "e"))
+            }
+            TypeDefKind::Result(r) => format!(
+                "match {e} {{ Ok(e) => Ok({}), Err(e) => Err({}) }}",
+                r.ok.as_ref()
+                    .map_or("*e".to_string(), |ty| self.owned_expr(ty, "e")),
+                r.err
+                    .as_ref()
+                    .map_or("*e".to_string(), |ty| self.owned_expr(ty, "e")),
+            ),
+            TypeDefKind::Tuple(t) => format!(
+                "({})",
+                t.types
+                    .iter()
+                    .enumerate()
+                    .map(|(i, ty)| format!("{},", self.owned_expr(ty, &format!("&({e}).{i}"))))
+                    .collect::<String>()
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns an expression borrowing `e`, a reference to an owned value of
+    /// type `ty`, as a borrowed value, or `None` if that would require
+    /// allocating such as for lists of strings.
+    fn borrowed_expr(&self, ty: &Type, e: &str) -> Option<String> {
+        let id = match ty {
+            _ if !self.type_has_list(ty) => return Some(self.copy_expr(ty, e)),
+            Type::String => return Some(format!("&**{e}")),
+            Type::Id(id) => *id,
+            _ => unreachable!(),
+        };
+        let ty = &self.resolve().types[id];
+        if ty.name.is_some()
+            && matches!(
+                ty.kind,
+                TypeDefKind::Record(_) | TypeDefKind::Variant(_) | TypeDefKind::Union(_)
+            )
+        {
+            return Some(format!("From::from({e})"));
+        }
+        Some(match &ty.kind {
+            TypeDefKind::Type(ty) => self.borrowed_expr(ty, e)?,
+            TypeDefKind::List(ty) if !self.type_has_list(ty) => format!("({e}).as_slice()"),
+            TypeDefKind::List(_) => return None,
+            TypeDefKind::Option(ty) => {
+                format!("({e}).as_ref().map(|e| {})", self.borrowed_expr(ty, "e")?)
+            }
+            TypeDefKind::Result(r) => {
+                let ok = match &r.ok {
+                    Some(ty) => self.borrowed_expr(ty, "e")?,
+                    None => "*e".to_string(),
+                };
+                let err = match &r.err {
+                    Some(ty) => self.borrowed_expr(ty, "e")?,
+                    None => "*e".to_string(),
+                };
+                format!("match {e} {{ Ok(e) => Ok({ok}), Err(e) => Err({err}) }}")
+            }
+            TypeDefKind::Tuple(t) => {
+                let mut fields = String::new();
+                for (i, ty) in t.types.iter().enumerate() {
+                    fields.push_str(&self.borrowed_expr(ty, &format!("&({e}).{i}"))?);
+                    fields.push(',');
+                }
+                format!("({fields})")
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    /// Returns an expression copying `e`, a reference to a value of type `ty`
+    /// without lists, which is only `Copy` if it contains no remapped types.
+    fn copy_expr(&self, ty: &Type, e: &str) -> String {
+        if self.has_remapped_type(ty) {
+            format!("Clone::clone({e})")
+        } else {
+            format!("*{e}")
+        }
+    }
+
+    fn type_has_list(&self, ty: &Type) -> bool {
+        match ty {
+            _ if self.remapped_type(ty).is_some() => false,
+            Type::String => true,
+            Type::Id(id) => self.info(*id).has_list,
+            _ => false,
+        }
     }
 
     fn print_rust_enum_debug<'b>(
@@ -1003,10 +1457,14 @@ pub trait RustGenerator<'a> {
         self.push_str("#[repr(");
         self.int_repr(enum_.tag());
         self.push_str(")]\n#[derive(Clone, Copy, PartialEq, Eq)]\n");
+        let serde = self.print_serde_derives(&info, None);
         self.push_str(&format!("pub enum {} {{\n", name.to_upper_camel_case()));
         for case in enum_.cases.iter() {
             self.rustdoc(&case.docs);
             self.push_str(&case_attr(case));
+            if serde {
+                self.push_str(&format!("#[serde(rename = \"{}\")]\n", case.name));
+            }
             self.push_str(&case.name.to_upper_camel_case());
             self.push_str(",\n");
         }
@@ -1227,6 +1685,11 @@ pub struct FnSig {
     pub generics: Option<String>,
     pub self_arg: Option<String>,
     pub self_is_first_param: bool,
+    /// Pass parameters accepted by `is_borrowable_param` as `&str`/`&[T]`.
+    pub borrow_canonical_params: bool,
+    /// Return the error of a `result` accepted by `convertible_error` as the
+    /// associated type named by `error_type_name`.
+    pub convert_errors: bool,
 }
 
 pub trait RustFunctionGenerator {
@@ -1465,6 +1928,45 @@ impl fmt::Display for RustFlagsRepr {
     }
 }
 
+/// Returns the ok and error types of `results` if it is a single `result` with
+/// an error type.
+pub fn convertible_error<'a>(
+    resolve: &'a Resolve,
+    results: &Results,
+) -> Option<(Option<&'a Type>, &'a Type)> {
+    let Some(Type::Id(id)) = results.iter_types().next().filter(|_| results.len() == 1) else {
+        return None;
+    };
+    match &resolve.types[*id].kind {
+        TypeDefKind::Result(Result_ { ok, err: Some(err) }) => Some((ok.as_ref(), err)),
+        _ => None,
+    }
+}
+
+/// The associated type of an exported trait naming the error returned by
+/// `func` with `FnSig::convert_errors`.
+pub fn error_type_name(func: &Function) -> String {
+    format!("{}Error", func.item_name().to_upper_camel_case())
+}
+
+/// Returns whether `ty` is a top-level `string` or anonymous `list<T>` whose
+/// canonical ABI representation can be borrowed directly as `&str`/`&[T]`.
+///
+/// Strings are only considered if `strings` is true.
+pub fn is_borrowable_param(resolve: &Resolve, ty: &Type, strings: bool) -> bool {
+    match ty {
+        Type::String => strings,
+        Type::Id(id) => {
+            let ty = &resolve.types[*id];
+            match &ty.kind {
+                TypeDefKind::List(element) => ty.name.is_none() && resolve.all_bits_valid(element),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
 pub fn dealias(resolve: &Resolve, mut id: TypeId) -> TypeId {
     loop {
         match &resolve.types[id].kind {
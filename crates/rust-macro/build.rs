@@ -0,0 +1,43 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Identifies the generator this macro is built with, for the keys of
+    // cached expansions. Within this repository, as used by git and path
+    // dependencies, that's the sources of the crates generating bindings, so
+    // that updating them without a version bump doesn't reuse stale
+    // expansions. Published crates can't change without a version bump, and
+    // these paths don't exist for them.
+    let mut hasher = DefaultHasher::new();
+    for krate in ["core", "rust", "rust-lib", "rust-macro"] {
+        let src = Path::new("..").join(krate).join("src");
+        if src.is_dir() {
+            println!("cargo:rerun-if-changed={}", src.display());
+            hash_dir(&src, &mut hasher);
+        }
+    }
+    println!(
+        "cargo:rustc-env=WIT_BINDGEN_BUILD_ID={:016x}",
+        hasher.finish()
+    );
+}
+
+fn hash_dir(dir: &Path, hasher: &mut DefaultHasher) {
+    let mut entries = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    entries.sort();
+    for path in entries {
+        path.file_name().hash(hasher);
+        if path.is_dir() {
+            hash_dir(&path, hasher);
+        } else {
+            fs::read(&path).unwrap().hash(hasher);
+        }
+    }
+}
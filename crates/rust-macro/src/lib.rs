@@ -1,11 +1,20 @@
+use anyhow::{bail, Context};
 use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use syn::parse::{Error, Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
 use syn::{braced, token, Token};
-use wit_bindgen_core::wit_parser::{PackageId, Resolve, UnresolvedPackage, WorldId};
-use wit_bindgen_rust::Opts;
+use wit_bindgen_core::wit_parser::{PackageId, PackageName, Resolve, UnresolvedPackage};
+use wit_bindgen_rust::{InvalidRemap, MissingExport, Opts};
 use wit_bindgen_rust_lib::Ownership;
+use wit_component::StringEncoding;
 
 #[proc_macro]
 pub fn generate(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
@@ -17,22 +26,51 @@ pub fn generate(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 
 struct Config {
     opts: Opts,
-    resolve: Resolve,
-    world: WorldId,
+    sources: Sources,
+    world: Option<syn::LitStr>,
+    /// The WIT files `sources` are parsed from.
     files: Vec<PathBuf>,
+    /// A hash of the invocation, of everything the expansion depends on.
+    key: u64,
+    exports_span: Option<Span>,
+    /// The spans of the WIT types of `remap_types`, to which errors about them
+    /// are reported.
+    remap_spans: HashMap<String, Span>,
+    /// Where to write the expansion, from the `debug_dir` option or the
+    /// `WIT_BINDGEN_DEBUG_DIR` environment variable.
+    debug_dir: Option<(PathBuf, Span)>,
+    /// Where to cache expansions with the `cache` option, under `OUT_DIR`.
+    cache_dir: Option<PathBuf>,
 }
 
-enum Source {
-    Path(String),
-    Inline(String),
+/// The WIT sources to parse, relative to `CARGO_MANIFEST_DIR`.
+///
+/// The world is selected from the `inline` package if there is one, and
+/// otherwise from the first of `paths`, defaulting to the `wit` directory.
+/// All other packages, as well as those in the `deps` directory of any
+/// directory in `paths`, are available as dependencies.
+#[derive(Default)]
+struct Sources {
+    paths: Vec<String>,
+    deps: Vec<(String, String)>,
+    inline: Option<syn::LitStr>,
+    /// The span of the option which specified the first source, to which
+    /// errors are reported.
+    span: Option<Span>,
 }
 
 impl Parse for Config {
     fn parse(input: ParseStream<'_>) -> Result<Self> {
         let call_site = Span::call_site();
+        let tokens = input.cursor().token_stream().to_string();
         let mut opts = Opts::default();
-        let mut world = None;
-        let mut source = None;
+        let mut world = None::<syn::LitStr>;
+        let mut sources = Sources::default();
+        let mut exports_span = None;
+        let mut remap_spans = HashMap::new();
+        let mut debug_dir =
+            std::env::var_os("WIT_BINDGEN_DEBUG_DIR").map(|dir| (PathBuf::from(dir), call_site));
+        let mut cache_dir = None;
 
         if input.peek(token::Brace) {
             let content;
@@ -40,96 +78,406 @@ impl Parse for Config {
             let fields = Punctuated::<Opt, Token![,]>::parse_terminated(&content)?;
             for field in fields.into_pairs() {
                 match field.into_value() {
-                    Opt::Path(s) => {
-                        if source.is_some() {
-                            return Err(Error::new(s.span(), "cannot specify second source"));
+                    Opt::Path(span, paths) => {
+                        if !sources.paths.is_empty() {
+                            return Err(Error::new(span, "cannot specify second `path`"));
                         }
-                        source = Some(Source::Path(s.value()));
+                        sources.paths = paths.iter().map(|s| s.value()).collect();
+                        sources.span.get_or_insert(span);
                     }
                     Opt::World(s) => {
                         if world.is_some() {
                             return Err(Error::new(s.span(), "cannot specify second world"));
                         }
-                        world = Some(s.value());
+                        world = Some(s);
                     }
                     Opt::Inline(s) => {
-                        if source.is_some() {
-                            return Err(Error::new(s.span(), "cannot specify second source"));
+                        if sources.inline.is_some() {
+                            return Err(Error::new(
+                                s.span(),
+                                "cannot specify second inline source",
+                            ));
                         }
-                        source = Some(Source::Inline(s.value()));
+                        sources.span.get_or_insert(s.span());
+                        sources.inline = Some(s);
                     }
+                    Opt::Deps(deps) => sources.deps.extend(
+                        deps.into_iter()
+                            .map(|dep| (dep.name.value(), dep.path.value())),
+                    ),
                     Opt::UseStdFeature => opts.std_feature = true,
                     Opt::RawStrings => opts.raw_strings = true,
                     Opt::Ownership(ownership) => opts.ownership = ownership,
                     Opt::Skip(list) => opts.skip.extend(list.iter().map(|i| i.value())),
                     Opt::RuntimePath(path) => opts.runtime_path = Some(path.value()),
-                    Opt::Exports(exports) => opts.exports.extend(
-                        exports
-                            .into_iter()
-                            .map(|export| (export.key.into(), serialize(export.value))),
-                    ),
+                    Opt::Exports(span, exports) => {
+                        exports_span.get_or_insert(span);
+                        opts.exports.extend(
+                            exports
+                                .into_iter()
+                                .map(|export| (export.key.into(), serialize(export.value))),
+                        );
+                    }
                     Opt::Stubs => {
                         opts.stubs = true;
                     }
                     Opt::ExportPrefix(prefix) => opts.export_prefix = Some(prefix.value()),
+                    Opt::OutlineHelpers => opts.outline_helpers = true,
+                    Opt::Threads => opts.threads = true,
+                    Opt::BorrowedExportParams => opts.borrowed_export_params = true,
+                    Opt::ExportMacro => opts.export_macro = true,
+                    Opt::MutableResources => opts.mutable_resources = true,
+                    Opt::StringEncoding(encoding) => opts.string_encoding = encoding,
+                    Opt::Reflection => opts.reflection = true,
+                    Opt::Dispatch => opts.dispatch = true,
+                    Opt::DynamicExports => opts.dynamic_exports = true,
+                    Opt::Serde => opts.serde = true,
+                    Opt::Instrument => opts.instrument = true,
+                    Opt::ErrorConversion => opts.error_conversion = true,
+                    Opt::Singleton => opts.singleton = true,
+                    Opt::AsyncExports => opts.async_exports = true,
+                    Opt::CaseConversions => opts.case_conversions = true,
+                    Opt::RecordBuilders => opts.record_builders = true,
+                    Opt::RemapTypes(remaps) => {
+                        for remap in remaps {
+                            remap_spans.insert(remap.wit.value(), remap.wit.span());
+                            opts.remap_types.push((
+                                remap.wit.value(),
+                                remap.rust.to_token_stream().to_string(),
+                            ));
+                        }
+                    }
+                    Opt::DebugDir(dir) => {
+                        let root = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+                        debug_dir = Some((Path::new(&root).join(dir.value()), dir.span()));
+                    }
+                    Opt::Cache(span) => match std::env::var_os("OUT_DIR") {
+                        Some(dir) => cache_dir = Some(Path::new(&dir).join("wit-bindgen-cache")),
+                        None => {
+                            return Err(Error::new(
+                                span,
+                                "`cache` requires `OUT_DIR` to be set, so the crate needs a \
+                                 build script",
+                            ))
+                        }
+                    },
                 }
             }
         } else {
-            world = input.parse::<Option<syn::LitStr>>()?.map(|s| s.value());
+            world = input.parse::<Option<syn::LitStr>>()?;
             if input.parse::<Option<syn::token::In>>()?.is_some() {
-                source = Some(Source::Path(input.parse::<syn::LitStr>()?.value()));
+                let path = input.parse::<syn::LitStr>()?;
+                sources.span = Some(path.span());
+                sources.paths = vec![path.value()];
             }
         }
-        let (resolve, pkg, files) =
-            parse_source(&source).map_err(|err| Error::new(call_site, format!("{err:?}")))?;
-        let world = resolve
-            .select_world(pkg, world.as_deref())
-            .map_err(|e| Error::new(call_site, format!("{e:?}")))?;
+        let files = source_files(&sources);
+
+        // Everything the expansion depends on is hashed up front, so that
+        // cached expansions are used without even parsing the WIT sources.
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        env!("WIT_BINDGEN_BUILD_ID").hash(&mut hasher);
+        std::env::var_os("CARGO_MANIFEST_DIR").hash(&mut hasher);
+        tokens.hash(&mut hasher);
+        for file in files.iter() {
+            file.hash(&mut hasher);
+            fs::read(file).ok().hash(&mut hasher);
+        }
+
         Ok(Config {
             opts,
-            resolve,
+            sources,
             world,
             files,
+            key: hasher.finish(),
+            exports_span,
+            remap_spans,
+            debug_dir,
+            cache_dir,
         })
     }
 }
 
-fn parse_source(source: &Option<Source>) -> anyhow::Result<(Resolve, PackageId, Vec<PathBuf>)> {
-    let mut resolve = Resolve::default();
+/// Reads the expansion cached in `dir`, returning the name of its file, its
+/// contents and their tokens, or `None` if it can't be read or parsed.
+fn read_cached(dir: &Path) -> Option<(String, String, TokenStream)> {
+    let entry = dir
+        .read_dir()
+        .ok()?
+        .flatten()
+        .find(|entry| entry.path().extension() != Some("tmp".as_ref()))?;
+    let src = fs::read_to_string(entry.path()).ok()?;
+    let tokens = src.parse().ok()?;
+    Some((entry.file_name().into_string().ok()?, src, tokens))
+}
+
+/// Caches the expansion `src` as `dir/name`, writing it to a temporary file
+/// first so that concurrent expansions never read it partly written.
+fn write_cached(dir: &Path, name: &str, src: &str) -> std::io::Result<()> {
+    static TMP: AtomicUsize = AtomicUsize::new(0);
+
+    fs::create_dir_all(dir)?;
+    let tmp = dir.join(format!(
+        "{name}.{}-{}.tmp",
+        std::process::id(),
+        TMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp, src)?;
+    let result = fs::rename(&tmp, dir.join(name));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Returns the WIT files `parse_source` parses for `sources`, which are
+/// those of every file and directory in their `paths` and `deps`, and of the
+/// packages in the `deps` directory of each of these directories.
+fn source_files(sources: &Sources) -> Vec<PathBuf> {
+    let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
     let mut files = Vec::new();
+    let deps = sources.deps.iter().map(|(_, path)| path.as_str());
+    for path in source_paths(sources).into_iter().chain(deps) {
+        let path = root.join(path);
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        files.extend(wit_files(&path));
+        let Ok(deps) = path.join("deps").read_dir() else {
+            continue;
+        };
+        for dep in deps.flatten() {
+            files.extend(wit_files(&dep.path()));
+        }
+    }
+    files.sort();
+    files.dedup();
+    return files;
+
+    fn wit_files(dir: &Path) -> Vec<PathBuf> {
+        let Ok(entries) = dir.read_dir() else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+                !path.is_dir() && (name.ends_with(".wit") || name.ends_with(".wit.md"))
+            })
+            .collect()
+    }
+}
+
+/// Returns the `paths` of `sources`, which default to the `wit` directory
+/// without an `inline` source.
+fn source_paths(sources: &Sources) -> Vec<&str> {
+    if sources.paths.is_empty() && sources.inline.is_none() {
+        return vec!["wit"];
+    }
+    sources.paths.iter().map(String::as_str).collect()
+}
+
+fn parse_source(sources: &Sources) -> anyhow::Result<(Resolve, PackageId)> {
     let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-    let mut parse = |path: &Path| -> anyhow::Result<_> {
-        if path.is_dir() {
-            let (pkg, sources) = resolve.push_dir(&path)?;
-            files = sources;
-            Ok(pkg)
+    let mut packages = BTreeMap::new();
+    let mut dirs = Vec::new();
+
+    // Packages which are listed explicitly must be unique, while those found
+    // in `deps` directories are skipped if they're already known, as several
+    // directories may well vendor the same dependencies.
+    let mut add = |pkg: UnresolvedPackage, explicit: bool| -> anyhow::Result<PackageName> {
+        let name = pkg.name.clone();
+        if packages.contains_key(&name) {
+            if explicit {
+                bail!("duplicate definitions of package `{name}` found");
+            }
         } else {
-            let pkg = UnresolvedPackage::parse_file(path)?;
-            files.extend(pkg.source_files().map(|s| s.to_owned()));
-            resolve.push(pkg)
+            packages.insert(name.clone(), pkg);
         }
+        Ok(name)
     };
-    let pkg = match source {
-        Some(Source::Inline(s)) => {
-            resolve.push(UnresolvedPackage::parse("macro-input".as_ref(), &s)?)?
+    let mut parse = |path: &Path| -> anyhow::Result<UnresolvedPackage> {
+        if path.is_dir() {
+            dirs.push(path.to_owned());
+            UnresolvedPackage::parse_dir(path)
+        } else {
+            UnresolvedPackage::parse_file(path)
         }
-        Some(Source::Path(s)) => parse(&root.join(&s))?,
-        None => parse(&root.join("wit"))?,
+        .with_context(|| format!("failed to parse package: {}", path.display()))
     };
 
-    Ok((resolve, pkg, files))
+    for (name, path) in sources.deps.iter() {
+        let pkg = parse(&root.join(path))?;
+        if pkg.name.to_string() != *name {
+            bail!(
+                "dependency `{name}` at `{path}` is actually package `{}`",
+                pkg.name
+            );
+        }
+        add(pkg, true)?;
+    }
+    let mut main = None;
+    for path in source_paths(sources) {
+        let name = add(parse(&root.join(path))?, true)?;
+        main.get_or_insert(name);
+    }
+    if let Some(s) = &sources.inline {
+        let pkg = UnresolvedPackage::parse(INLINE_PATH.as_ref(), &s.value())?;
+        main = Some(add(pkg, true)?);
+    }
+    for dir in dirs {
+        let deps = dir.join("deps");
+        if !deps.exists() {
+            continue;
+        }
+        for dep in deps.read_dir().context("failed to read directory")? {
+            let path = dep.context("failed to read directory iterator")?.path();
+            // Files in deps dir are ignored, like `Resolve::push_dir` does.
+            if path.is_dir() {
+                let pkg = UnresolvedPackage::parse_dir(&path)
+                    .with_context(|| format!("failed to parse package: {}", path.display()))?;
+                add(pkg, false)?;
+            }
+        }
+    }
+
+    // Add the packages in topological order, such that each one's
+    // dependencies are already present in `resolve`. Missing dependencies are
+    // left for `Resolve::push` to report with the span of their `use`.
+    let mut resolve = Resolve::default();
+    let names = packages.keys().cloned().collect::<Vec<_>>();
+    for name in names {
+        push(&name, &mut packages, &mut resolve, &mut HashSet::new())?;
+    }
+    let pkg = resolve.package_names[&main.unwrap()];
+    return Ok((resolve, pkg));
+
+    fn push(
+        name: &PackageName,
+        packages: &mut BTreeMap<PackageName, UnresolvedPackage>,
+        resolve: &mut Resolve,
+        visiting: &mut HashSet<PackageName>,
+    ) -> anyhow::Result<()> {
+        let Some(pkg) = packages.remove(name) else {
+            if visiting.contains(name) {
+                bail!("package `{name}` depends on itself");
+            }
+            return Ok(());
+        };
+        visiting.insert(name.clone());
+        for dep in pkg.foreign_deps.keys() {
+            push(dep, packages, resolve, visiting)?;
+        }
+        visiting.remove(name);
+        resolve.push(pkg)?;
+        Ok(())
+    }
+}
+
+/// The file name under which `inline` sources are parsed, and so reported in
+/// errors.
+const INLINE_PATH: &str = "macro-input";
+
+/// Converts an error from `parse_source` into one spanning the source option.
+///
+/// Errors within an `inline` source are narrowed down to the offending part of
+/// the string literal where the compiler supports it.
+fn source_error(err: &anyhow::Error, sources: &Sources) -> Error {
+    let msg = format!("{err:#}");
+    if let Some(lit) = &sources.inline {
+        if let Some(error) = inline_error(&msg, lit) {
+            return error;
+        }
+    }
+    Error::new(sources.span.unwrap_or_else(Span::call_site), msg)
+}
+
+/// Returns an error spanning the part of `lit` a rendered WIT error points
+/// at, if the compiler supports narrowing spans, with just its message.
+fn inline_error(msg: &str, lit: &syn::LitStr) -> Option<Error> {
+    let token = lit.token();
+    let (range, message) = inline_error_range(msg, &lit.value(), &token.to_string())?;
+    Some(Error::new(token.subspan(range)?, message))
+}
+
+/// Parses the location out of a rendered WIT error such as
+///
+/// ```text
+/// expected `{`, found `}`
+///      --> macro-input:3:14
+///       |
+///     3 |     world foo }
+///       |              ^
+/// ```
+///
+/// and returns the byte range of that location within `token`, the source of
+/// a string literal with the value `value`, along with the message on the
+/// error's first lines.
+///
+/// The error is only available rendered, as `wit-parser` doesn't expose its
+/// location otherwise. Its column counts bytes from the start of the line,
+/// and its marker one character of the location per `^` or `-`.
+fn inline_error_range(msg: &str, value: &str, token: &str) -> Option<(Range<usize>, String)> {
+    let lines = msg.lines().collect::<Vec<_>>();
+    let at = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with(&format!("--> {INLINE_PATH}:")))?;
+    let (line, col) = lines[at].trim_start()[INLINE_PATH.len() + 5..].split_once(':')?;
+    let (line, col) = (line.parse::<usize>().ok()?, col.parse::<usize>().ok()?);
+    let marker = lines.get(at + 3)?;
+    let len = marker.matches(['^', '-']).count().max(1);
+
+    // Byte offsets of the error within the literal's value, and then within
+    // its token, which is only possible without escapes.
+    let line_start = value
+        .split_inclusive('\n')
+        .take(line.checked_sub(1)?)
+        .map(str::len)
+        .sum::<usize>();
+    let start = line_start + col.checked_sub(1)?;
+    let end = value
+        .get(start..)?
+        .char_indices()
+        .nth(len)
+        .map_or(value.len(), |(i, _)| start + i);
+    let hashes = token
+        .strip_prefix('r')
+        .map(|t| t.len() - t.trim_start_matches('#').len());
+    let (prefix, suffix) = hashes.map_or((1, 1), |n| (n + 2, n + 1));
+    if token.get(prefix..token.len().checked_sub(suffix)?)? != value {
+        return None;
+    }
+    Some((prefix + start..prefix + end, lines[..at].join("\n")))
 }
 
 impl Config {
     fn expand(self) -> Result<TokenStream> {
-        let mut files = Default::default();
-        let mut generator = self.opts.build();
-        generator
-            .generate(&self.resolve, self.world, &mut files)
-            .map_err(|e| Error::new(Span::call_site(), e))?;
-        let (_, src) = files.iter().next().unwrap();
-        let src = std::str::from_utf8(src).unwrap();
-        let mut contents = src.parse::<TokenStream>().unwrap();
+        // Each expansion is cached in a directory of its own, under the name
+        // the generator gave it.
+        let cached = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}", self.key)));
+        // Entries which can't be read or parsed are regenerated.
+        let (name, src, mut contents) = match cached.as_deref().and_then(read_cached) {
+            Some(expansion) => expansion,
+            None => {
+                let (name, src) = self.generate()?;
+                // Failing to cache only costs regenerating next time.
+                if let Some(dir) = &cached {
+                    let _ = write_cached(dir, &name, &src);
+                }
+                let contents = src.parse::<TokenStream>().unwrap();
+                (name, src, contents)
+            }
+        };
+        if let Some((dir, span)) = &self.debug_dir {
+            self.dump(dir, &name, &src)
+                .map_err(|e| Error::new(*span, format!("{e:#}")))?;
+        }
 
         // Include a dummy `include_str!` for any files we read so rustc knows that
         // we depend on the contents of those files.
@@ -140,9 +488,65 @@ impl Config {
                     .unwrap(),
             );
         }
+        // Likewise for the variable enabling dumps, which are otherwise only
+        // written when the crate is rebuilt for another reason.
+        contents.extend(
+            "const _: Option<&str> = option_env!(\"WIT_BINDGEN_DEBUG_DIR\");\n"
+                .parse::<TokenStream>()
+                .unwrap(),
+        );
 
         Ok(contents)
     }
+
+    /// Generates the expansion, returning the name of its file and its
+    /// contents.
+    fn generate(&self) -> Result<(String, String)> {
+        let call_site = Span::call_site();
+        let (resolve, pkg) =
+            parse_source(&self.sources).map_err(|err| source_error(&err, &self.sources))?;
+        let world = resolve
+            .select_world(pkg, self.world.as_ref().map(|s| s.value()).as_deref())
+            .map_err(|e| {
+                let span = self.world.as_ref().map_or(call_site, |s| s.span());
+                Error::new(span, format!("{e:#}"))
+            })?;
+        let mut files = Default::default();
+        let mut generator = self.opts.clone().build();
+        generator
+            .generate(&resolve, world, &mut files)
+            .map_err(|e| {
+                let span = if e.is::<MissingExport>() {
+                    self.exports_span.unwrap_or(call_site)
+                } else if let Some(remap) = e.downcast_ref::<InvalidRemap>() {
+                    self.remap_spans[&remap.key]
+                } else {
+                    call_site
+                };
+                Error::new(span, format!("{e:#}"))
+            })?;
+        let (name, src) = files.iter().next().unwrap();
+        Ok((
+            name.to_owned(),
+            std::str::from_utf8(src).unwrap().to_owned(),
+        ))
+    }
+
+    /// Writes the expansion `name` to `<dir>/<name>-<key>.rs`, formatted with
+    /// `rustfmt` if it's available, where `key` tells invocations apart.
+    fn dump(&self, dir: &Path, name: &str, src: &str) -> anyhow::Result<()> {
+        let stem = name.strip_suffix(".rs").unwrap_or(name);
+        let path = dir.join(format!("{stem}-{:016x}.rs", self.key));
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create directory: {}", dir.display()))?;
+        fs::write(&path, src).with_context(|| format!("failed to write: {}", path.display()))?;
+        // Like the generator itself, as that's the edition it generates for.
+        let _ = std::process::Command::new("rustfmt")
+            .arg("--edition=2018")
+            .arg(&path)
+            .output();
+        Ok(())
+    }
 }
 
 mod kw {
@@ -152,11 +556,31 @@ mod kw {
     syn::custom_keyword!(world);
     syn::custom_keyword!(path);
     syn::custom_keyword!(inline);
+    syn::custom_keyword!(deps);
     syn::custom_keyword!(ownership);
     syn::custom_keyword!(runtime_path);
     syn::custom_keyword!(exports);
     syn::custom_keyword!(stubs);
     syn::custom_keyword!(export_prefix);
+    syn::custom_keyword!(outline_helpers);
+    syn::custom_keyword!(threads);
+    syn::custom_keyword!(borrowed_export_params);
+    syn::custom_keyword!(export_macro);
+    syn::custom_keyword!(mutable_resources);
+    syn::custom_keyword!(string_encoding);
+    syn::custom_keyword!(reflection);
+    syn::custom_keyword!(dispatch);
+    syn::custom_keyword!(dynamic_exports);
+    syn::custom_keyword!(serde);
+    syn::custom_keyword!(instrument);
+    syn::custom_keyword!(error_conversion);
+    syn::custom_keyword!(debug_dir);
+    syn::custom_keyword!(cache);
+    syn::custom_keyword!(singleton);
+    syn::custom_keyword!(async_exports);
+    syn::custom_keyword!(remap_types);
+    syn::custom_keyword!(case_conversions);
+    syn::custom_keyword!(record_builders);
 }
 
 #[derive(Clone)]
@@ -186,6 +610,36 @@ impl From<ExportKey> for wit_bindgen_rust::ExportKey {
     }
 }
 
+struct Dep {
+    name: syn::LitStr,
+    path: syn::LitStr,
+}
+
+impl Parse for Dep {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let path = input.parse()?;
+        Ok(Self { name, path })
+    }
+}
+
+/// A WIT type and the Rust type used in its place, as in
+/// `"list<u8>": bytes::Bytes`.
+struct Remap {
+    wit: syn::LitStr,
+    rust: syn::Type,
+}
+
+impl Parse for Remap {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let wit = input.parse()?;
+        input.parse::<Token![:]>()?;
+        let rust = input.parse()?;
+        Ok(Self { wit, rust })
+    }
+}
+
 #[derive(Clone)]
 struct Export {
     key: ExportKey,
@@ -203,29 +657,63 @@ impl Parse for Export {
 
 enum Opt {
     World(syn::LitStr),
-    Path(syn::LitStr),
+    Path(Span, Vec<syn::LitStr>),
     Inline(syn::LitStr),
+    Deps(Vec<Dep>),
     UseStdFeature,
     RawStrings,
     Skip(Vec<syn::LitStr>),
     Ownership(Ownership),
     RuntimePath(syn::LitStr),
-    Exports(Vec<Export>),
+    Exports(Span, Vec<Export>),
     Stubs,
     ExportPrefix(syn::LitStr),
+    OutlineHelpers,
+    Threads,
+    BorrowedExportParams,
+    ExportMacro,
+    MutableResources,
+    StringEncoding(StringEncoding),
+    Reflection,
+    Dispatch,
+    DynamicExports,
+    Serde,
+    Instrument,
+    ErrorConversion,
+    DebugDir(syn::LitStr),
+    Cache(Span),
+    Singleton,
+    AsyncExports,
+    RemapTypes(Vec<Remap>),
+    CaseConversions,
+    RecordBuilders,
 }
 
 impl Parse for Opt {
     fn parse(input: ParseStream<'_>) -> Result<Self> {
         let l = input.lookahead1();
         if l.peek(kw::path) {
-            input.parse::<kw::path>()?;
+            let span = input.parse::<kw::path>()?.span;
             input.parse::<Token![:]>()?;
-            Ok(Opt::Path(input.parse()?))
+            if input.peek(token::Bracket) {
+                let contents;
+                syn::bracketed!(contents in input);
+                let list = Punctuated::<_, Token![,]>::parse_terminated(&contents)?;
+                Ok(Opt::Path(span, list.into_iter().collect()))
+            } else {
+                Ok(Opt::Path(span, vec![input.parse()?]))
+            }
         } else if l.peek(kw::inline) {
             input.parse::<kw::inline>()?;
             input.parse::<Token![:]>()?;
             Ok(Opt::Inline(input.parse()?))
+        } else if l.peek(kw::deps) {
+            input.parse::<kw::deps>()?;
+            input.parse::<Token![:]>()?;
+            let contents;
+            syn::braced!(contents in input);
+            let list = Punctuated::<_, Token![,]>::parse_terminated(&contents)?;
+            Ok(Opt::Deps(list.into_iter().collect()))
         } else if l.peek(kw::world) {
             input.parse::<kw::world>()?;
             input.parse::<Token![:]>()?;
@@ -275,12 +763,12 @@ impl Parse for Opt {
                 }
             }))
         } else if l.peek(kw::exports) {
-            input.parse::<kw::exports>()?;
+            let span = input.parse::<kw::exports>()?.span;
             input.parse::<Token![:]>()?;
             let contents;
             syn::braced!(contents in input);
             let list = Punctuated::<_, Token![,]>::parse_terminated(&contents)?;
-            Ok(Opt::Exports(list.iter().cloned().collect()))
+            Ok(Opt::Exports(span, list.iter().cloned().collect()))
         } else if l.peek(kw::skip) {
             input.parse::<kw::skip>()?;
             input.parse::<Token![:]>()?;
@@ -299,6 +787,74 @@ impl Parse for Opt {
             input.parse::<kw::export_prefix>()?;
             input.parse::<Token![:]>()?;
             Ok(Opt::ExportPrefix(input.parse()?))
+        } else if l.peek(kw::outline_helpers) {
+            input.parse::<kw::outline_helpers>()?;
+            Ok(Opt::OutlineHelpers)
+        } else if l.peek(kw::threads) {
+            input.parse::<kw::threads>()?;
+            Ok(Opt::Threads)
+        } else if l.peek(kw::borrowed_export_params) {
+            input.parse::<kw::borrowed_export_params>()?;
+            Ok(Opt::BorrowedExportParams)
+        } else if l.peek(kw::export_macro) {
+            input.parse::<kw::export_macro>()?;
+            Ok(Opt::ExportMacro)
+        } else if l.peek(kw::mutable_resources) {
+            input.parse::<kw::mutable_resources>()?;
+            Ok(Opt::MutableResources)
+        } else if l.peek(kw::string_encoding) {
+            input.parse::<kw::string_encoding>()?;
+            input.parse::<Token![:]>()?;
+            let encoding = input.parse::<syn::LitStr>()?;
+            Ok(Opt::StringEncoding(
+                encoding
+                    .value()
+                    .parse()
+                    .map_err(|e| Error::new(encoding.span(), e))?,
+            ))
+        } else if l.peek(kw::reflection) {
+            input.parse::<kw::reflection>()?;
+            Ok(Opt::Reflection)
+        } else if l.peek(kw::dispatch) {
+            input.parse::<kw::dispatch>()?;
+            Ok(Opt::Dispatch)
+        } else if l.peek(kw::dynamic_exports) {
+            input.parse::<kw::dynamic_exports>()?;
+            Ok(Opt::DynamicExports)
+        } else if l.peek(kw::serde) {
+            input.parse::<kw::serde>()?;
+            Ok(Opt::Serde)
+        } else if l.peek(kw::instrument) {
+            input.parse::<kw::instrument>()?;
+            Ok(Opt::Instrument)
+        } else if l.peek(kw::error_conversion) {
+            input.parse::<kw::error_conversion>()?;
+            Ok(Opt::ErrorConversion)
+        } else if l.peek(kw::debug_dir) {
+            input.parse::<kw::debug_dir>()?;
+            input.parse::<Token![:]>()?;
+            Ok(Opt::DebugDir(input.parse()?))
+        } else if l.peek(kw::cache) {
+            Ok(Opt::Cache(input.parse::<kw::cache>()?.span))
+        } else if l.peek(kw::singleton) {
+            input.parse::<kw::singleton>()?;
+            Ok(Opt::Singleton)
+        } else if l.peek(kw::async_exports) {
+            input.parse::<kw::async_exports>()?;
+            Ok(Opt::AsyncExports)
+        } else if l.peek(kw::remap_types) {
+            input.parse::<kw::remap_types>()?;
+            input.parse::<Token![:]>()?;
+            let contents;
+            syn::braced!(contents in input);
+            let list = Punctuated::<_, Token![,]>::parse_terminated(&contents)?;
+            Ok(Opt::RemapTypes(list.into_iter().collect()))
+        } else if l.peek(kw::case_conversions) {
+            input.parse::<kw::case_conversions>()?;
+            Ok(Opt::CaseConversions)
+        } else if l.peek(kw::record_builders) {
+            input.parse::<kw::record_builders>()?;
+            Ok(Opt::RecordBuilders)
         } else {
             Err(l.error())
         }
@@ -324,3 +880,110 @@ fn serialize(path: syn::Path) -> String {
         serialized
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{inline_error_range, Config, INLINE_PATH};
+    use std::fs;
+    use wit_bindgen_core::wit_parser::UnresolvedPackage;
+
+    /// Returns the text of `source`, as a raw string literal, spanned by the
+    /// error parsing it, along with the error's message.
+    fn error_at(source: &str) -> Option<(String, String)> {
+        let err = UnresolvedPackage::parse(INLINE_PATH.as_ref(), source).err()?;
+        let token = format!("r#\"{source}\"#");
+        let (range, message) = inline_error_range(&format!("{err:#}"), source, &token)?;
+        Some((token[range].to_string(), message))
+    }
+
+    #[test]
+    fn spans_the_error() {
+        let (text, message) = error_at("package my:inline\n\nworld foo }\n").unwrap();
+        assert_eq!(text, "}");
+        assert_eq!(message, "expected '{', found '}'");
+    }
+
+    #[test]
+    fn spans_whole_names() {
+        let source = "package my:inline\n\nworld foo {\n  import f: func() -> missing\n}\n";
+        let (text, message) = error_at(source).unwrap();
+        assert_eq!(text, "missing");
+        assert_eq!(message, "name `missing` is not defined");
+    }
+
+    #[test]
+    fn counts_columns_in_bytes() {
+        let source = "package my:inline\n\n/* é ü */ world foo }\n";
+        assert_eq!(error_at(source).unwrap().0, "}");
+        let source = "package my:inline\n\nworld foo {\n  import f: func(a: ééé)\n}\n";
+        assert_eq!(error_at(source).unwrap().0, "é");
+    }
+
+    #[test]
+    fn requires_unescaped_literals() {
+        let source = "package my:inline\n\nworld foo }\n";
+        let err = UnresolvedPackage::parse(INLINE_PATH.as_ref(), source)
+            .err()
+            .unwrap();
+        let token = format!("{source:?}");
+        assert!(inline_error_range(&format!("{err:#}"), source, &token).is_none());
+    }
+
+    #[test]
+    fn caches_expansions() {
+        let out_dir =
+            std::env::temp_dir().join(format!("wit-bindgen-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&out_dir);
+        fs::create_dir_all(&out_dir).unwrap();
+        std::env::set_var("OUT_DIR", &out_dir);
+        let wit = out_dir.join("cached.wit");
+        let expand = || {
+            let input = format!("{{ path: {:?}, cache, stubs }}", wit.to_str().unwrap());
+            let config = syn::parse_str::<Config>(&input).unwrap();
+            config.expand().unwrap().to_string()
+        };
+
+        fs::write(
+            &wit,
+            "package my:cache\nworld cached { export f: func() }\n",
+        )
+        .unwrap();
+        let expansion = expand();
+        assert!(expansion.contains("fn f"), "{expansion}");
+        let cache = out_dir.join("wit-bindgen-cache");
+        let entries = fs::read_dir(&cache).unwrap().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        let entry = entries[0].as_ref().unwrap().path();
+        let cached = entry.join("cached.rs");
+        assert!(cached.exists());
+        // The temporary file the expansion was written to is renamed away.
+        assert_eq!(fs::read_dir(&entry).unwrap().count(), 1);
+
+        // A hit doesn't parse the WIT again, let alone generate bindings.
+        fs::write(&cached, "const FROM_CACHE: () = ();").unwrap();
+        assert!(expand().contains("FROM_CACHE"));
+
+        // Leftover temporary files aren't taken for the expansion.
+        fs::write(entry.join("cached.rs.0-0.tmp"), "const PARTIAL").unwrap();
+        assert!(expand().contains("FROM_CACHE"));
+        fs::remove_file(entry.join("cached.rs.0-0.tmp")).unwrap();
+
+        // Entries which don't parse are regenerated rather than panicking.
+        fs::write(&cached, "const FROM_CACHE: () = (").unwrap();
+        let expansion = expand();
+        assert!(expansion.contains("fn f"), "{expansion}");
+        assert!(!fs::read_to_string(&cached).unwrap().contains("FROM_CACHE"));
+
+        // Changing the WIT misses the cache.
+        fs::write(
+            &wit,
+            "package my:cache\nworld cached { export g: func() }\n",
+        )
+        .unwrap();
+        let expansion = expand();
+        assert!(expansion.contains("fn g"), "{expansion}");
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 2);
+
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
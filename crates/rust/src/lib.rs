@@ -1,7 +1,7 @@
-use anyhow::{anyhow, Result};
+use anyhow::{bail, Result};
 use heck::*;
 use std::collections::{BTreeMap, HashMap, HashSet};
-use std::fmt::Write as _;
+use std::fmt::{self, Write as _};
 use std::io::{Read, Write};
 use std::mem;
 use std::process::{Command, Stdio};
@@ -11,9 +11,10 @@ use wit_bindgen_core::{
     WorldGenerator,
 };
 use wit_bindgen_rust_lib::{
-    dealias, int_repr, to_rust_ident, wasm_type, FnSig, Ownership, RustFlagsRepr,
-    RustFunctionGenerator, RustGenerator, TypeMode,
+    convertible_error, dealias, int_repr, is_borrowable_param, to_rust_ident, wasm_type, FnSig,
+    Ownership, RustFlagsRepr, RustFunctionGenerator, RustGenerator, TypeMode,
 };
+use wit_component::StringEncoding;
 
 #[derive(Default, Copy, Clone, PartialEq, Eq)]
 enum Direction {
@@ -39,12 +40,39 @@ struct RustWasm {
     types: Types,
     src: Source,
     opts: Opts,
-    import_modules: BTreeMap<Option<PackageName>, Vec<String>>,
-    export_modules: BTreeMap<Option<PackageName>, Vec<String>>,
+    import_modules: BTreeMap<Option<PackageName>, Vec<(String, String)>>,
+    export_modules: BTreeMap<Option<PackageName>, Vec<(String, String)>>,
     skip: HashSet<String>,
     interface_names: HashMap<InterfaceId, String>,
     resources: HashMap<TypeId, ResourceInfo>,
     import_funcs_called: bool,
+    /// Helpers emitted with `Opts::outline_helpers`, by the module they're
+    /// defined in, and the names taken by helpers in each module.
+    helpers: HashMap<(HelperModule, TypeId, HelperKind), Helper>,
+    helper_names: HashMap<HelperModule, HashSet<String>>,
+    /// Functions exported from the expansion of the `export!` macro when
+    /// `Opts::export_macro` is enabled.
+    export_macro_shims: Vec<String>,
+    /// Names and paths of the shims of imported and exported functions when
+    /// `Opts::dispatch` is enabled.
+    dispatch_imports: Vec<(String, String)>,
+    dispatch_exports: Vec<(String, String)>,
+    /// Rust types substituted for WIT types with `Opts::remap_types`.
+    remapped: HashMap<Type, String>,
+    /// Remapped list types by their element type, as lowering a list doesn't
+    /// tell which list type it is.
+    remapped_lists: HashMap<Type, TypeId>,
+    /// The types implementing exports with `Opts::singleton`, whose instances
+    /// are `__SINGLETON_{index}` at the root of the bindings.
+    singletons: Vec<String>,
+}
+
+#[cfg(feature = "clap")]
+fn parse_remap_type(s: &str) -> Result<(String, String), String> {
+    let (wit, rust) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected string of form `<wit-type>=<rust-type>`; got `{s}`"))?;
+    Ok((wit.to_owned(), rust.to_owned()))
 }
 
 #[cfg(feature = "clap")]
@@ -75,6 +103,41 @@ pub enum ExportKey {
     Name(String),
 }
 
+/// Error returned when an export has no implementation listed in
+/// `Opts::exports` and `Opts::stubs` is off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingExport {
+    pub key: ExportKey,
+}
+
+impl fmt::Display for MissingExport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            ExportKey::World => write!(f, "export parameter required for `world`"),
+            ExportKey::Name(name) => write!(f, "export parameter required for `{name}`"),
+        }
+    }
+}
+
+impl std::error::Error for MissingExport {}
+
+/// Error returned when an entry of `Opts::remap_types` doesn't name a WIT
+/// type which can be remapped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRemap {
+    /// The WIT type of the entry.
+    pub key: String,
+    reason: String,
+}
+
+impl fmt::Display for InvalidRemap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl std::error::Error for InvalidRemap {}
+
 #[derive(Default, Debug, Clone)]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct Opts {
@@ -137,6 +200,186 @@ pub struct Opts {
     /// This defaults to `wit_bindgen::rt`.
     #[cfg_attr(feature = "clap", arg(long))]
     pub runtime_path: Option<String>,
+
+    /// If true, the code to lift, lower, and deallocate values of named types
+    /// is emitted once per type and module as an `#[inline(never)]` helper
+    /// function rather than being inlined at every use, both for arguments
+    /// and results of functions and for elements of lists.
+    ///
+    /// This trades a small amount of runtime performance for smaller code
+    /// size in worlds which use the same types in many functions.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub outline_helpers: bool,
+
+    /// If true, generate bindings which are safe to call from multiple threads
+    /// sharing the same linear memory, as with wasi-threads.
+    ///
+    /// Exported functions then use a thread-local return area instead of a
+    /// per-module static, which requires `std`, so this can't be combined
+    /// with `std_feature`. Imports always use a return area on the stack.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub threads: bool,
+
+    /// If true, exported functions receive `string` and canonical `list<T>`
+    /// parameters as `&str` and `&[T]` borrowing the argument buffer instead
+    /// of as an owned `String` or `Vec<T>`.
+    ///
+    /// The buffer is deallocated once the exported function returns.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub borrowed_export_params: bool,
+
+    /// If true, bindings are written as a module tree rooted at
+    /// `<world>/mod.rs` with one file per package and per interface instead
+    /// of as a single `<world>.rs` file.
+    ///
+    /// This is not supported by the `generate!` macro.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub split_modules: bool,
+
+    /// If true, exported functions are not bound to the types listed in
+    /// `exports` but are instead registered by invoking the generated
+    /// `export!(MyType)` macro, where `MyType` implements the generated
+    /// traits. `exports` must then be empty.
+    ///
+    /// Exported resources are implemented by associated types of the
+    /// interface traits, as in `type File = MyFile;`, and their `Rep*` types
+    /// box these values, which `get::<MyFile>()` returns.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub export_macro: bool,
+
+    /// If true, exported resources are stored in a `RefCell` so that their
+    /// methods take `&mut self`.
+    ///
+    /// `Own*` handles then dereference to the `RefCell` and borrowed handles
+    /// are passed as `&RefCell<Rep>`. A call which would alias a resource
+    /// already mutably borrowed, such as a re-entrant method call, panics.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub mutable_resources: bool,
+
+    /// The encoding strings use in the canonical ABI.
+    ///
+    /// With encodings other than UTF-8 strings are transcoded to and from
+    /// `String`, or with `raw_strings` are passed as UTF-16 code units in a
+    /// `Vec<u16>` or `&[u16]`.
+    #[cfg_attr(feature = "clap", arg(long, default_value_t = StringEncoding::default()))]
+    pub string_encoding: StringEncoding,
+
+    /// If true, a `WORLD` constant describing the interfaces, functions,
+    /// resources, and docs the world imports and exports is generated, using
+    /// the types in `wit_bindgen::rt::reflect`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub reflection: bool,
+
+    /// If true, a `DISPATCH` constant maps the name of every import and export
+    /// to a shim calling it with the dynamically typed `wit_bindgen::rt::Val`,
+    /// which is lowered and lifted by the function's canonical ABI glue.
+    ///
+    /// Functions using resources, futures, or streams are left out, as `Val`
+    /// can't represent them. This can't be used with `export_macro` or
+    /// `singleton`, whose implementations of exports can't be called by name.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub dispatch: bool,
+
+    /// If true, exports are implemented by the types in `exports` with
+    /// `wit_bindgen::rt::dynamic::Handler`, which is called with `Val`s for
+    /// every export, instead of the generated traits.
+    ///
+    /// Exports using resources, futures, or streams can't be implemented this
+    /// way, and this can't be used with `export_macro` or `singleton`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub dynamic_exports: bool,
+
+    /// If true, generated types implement `serde`'s `Serialize` and
+    /// `Deserialize` using the names of fields and cases in WIT, with enums
+    /// serialized as strings and flags as lists of names. Union cases, having
+    /// no names, are tagged with their index.
+    ///
+    /// Records, variants, and unions containing resources are rejected, and
+    /// types borrowing their contents only implement `Serialize`. This
+    /// requires the `serde` feature of the `wit-bindgen` crate.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub serde: bool,
+
+    /// If true, every call of an imported or exported function is reported to
+    /// the hook set with `wit_bindgen::rt::instrument::set_hook`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub instrument: bool,
+
+    /// If true, exported functions returning a `result<T, E>` return
+    /// `Result<T, Self::{Function}Error>` instead, with an associated type
+    /// bound by `Into<E>` which the bindings convert, such that domain errors
+    /// can be returned directly if they convert to `E`.
+    ///
+    /// Errors of type `string` are instead bound by `Display`, so that for
+    /// example an `anyhow::Error` can be returned and its message used.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub error_conversion: bool,
+
+    /// If true, exported functions take `&mut self`, an instance of the type
+    /// implementing their trait which is created with `Default` the first time
+    /// any of them is called, so that it can hold the component's state.
+    ///
+    /// A type implementing several exported interfaces, or the world, has one
+    /// instance shared by all of them. Calling an export while another one is
+    /// running, which borrows the instance, panics. This can't be used with
+    /// `threads`, as calls from several threads would do so.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub singleton: bool,
+
+    /// If true, the functions of export traits are `async fn`, whose futures
+    /// are run to completion with `wit_bindgen::rt::executor::block_on` before
+    /// the export returns.
+    ///
+    /// Components can't wait on the host yet, so these futures may only await
+    /// futures which are woken while polled, such as ones completed by other
+    /// parts of the component. This can't be used with `dynamic_exports`,
+    /// whose `Handler` isn't async.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub async_exports: bool,
+
+    /// If true, enums implement `TryFrom` of `u8`, `u16` and `u32`
+    /// discriminants, and `FromStr` and `Display` with the WIT names of their
+    /// cases, except for error enums which keep their own `Display`. As that
+    /// also shows the case's code, error enums don't round-trip through
+    /// `to_string().parse()`; their `name()` is the WIT name to parse instead.
+    ///
+    /// Flags get `wit_names` iterating over the WIT names of the flags set,
+    /// `from_wit_name` looking up a single flag, and `FromStr` and `Display`
+    /// with comma-separated names. Parse errors are
+    /// `wit_bindgen::rt::names::UnknownCase`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub case_conversions: bool,
+
+    /// If true, records whose fields all have a natural default, such as
+    /// options, lists, numbers, strings, and records of those, implement
+    /// `Default` and get a `builder()` returning a `{Record}Builder`.
+    ///
+    /// Builders have a `#[must_use]` setter for each field, taking the payload
+    /// of `option` fields, and `build` returning the record.
+    #[cfg_attr(feature = "clap", arg(long))]
+    pub record_builders: bool,
+
+    /// Rust types used in place of WIT types, as pairs of a WIT type and the
+    /// path of the Rust type replacing it everywhere in the bindings.
+    ///
+    /// Named WIT types are written as their path, such as
+    /// `wasi:clocks/wall-clock/datetime`, or just their name if defined in the
+    /// world. `string`, and anonymous types such as `list<u8>` or
+    /// `tuple<u64, u64>`, remap every use of that type; lists are only
+    /// remapped this way, rather than by a name given to them.
+    ///
+    /// The bindings define a `WitRemap<T>` trait at their root, converting
+    /// between a Rust type and `T`, the type which would have been generated
+    /// for the WIT type it replaces, and it must be implemented for every
+    /// remapped type. Paths should be absolute as they're used from nested
+    /// modules. Options deriving traits for generated types, such as `serde`,
+    /// or converting their values, such as `dispatch`, require remapped types
+    /// to implement those too.
+    #[cfg_attr(
+        feature = "clap",
+        arg(long = "remap-type", value_parser = parse_remap_type, value_name = "WIT=RUST")
+    )]
+    pub remap_types: Vec<(String, String)>,
 }
 
 impl Opts {
@@ -144,7 +387,7 @@ impl Opts {
         let mut r = RustWasm::new();
         r.skip = self.skip.iter().cloned().collect();
         r.opts = self;
-        Box::new(r)
+        Box::new(Validated(r))
     }
 }
 
@@ -173,10 +416,11 @@ impl RustWasm {
             resolve,
             return_pointer_area_size: 0,
             return_pointer_area_align: 0,
+            helpers_src: Source::default(),
         }
     }
 
-    fn emit_modules(&mut self, modules: &BTreeMap<Option<PackageName>, Vec<String>>) {
+    fn emit_modules(&mut self, modules: &BTreeMap<Option<PackageName>, Vec<(String, String)>>) {
         let mut map = BTreeMap::new();
         for (pkg, modules) in modules {
             match pkg {
@@ -188,8 +432,8 @@ impl RustWasm {
                     assert!(prev.is_none());
                 }
                 None => {
-                    for module in modules {
-                        uwriteln!(self.src, "{module}");
+                    for (snake, module) in modules {
+                        uwriteln!(self.src, "{}", inline_module(snake, module));
                     }
                 }
             }
@@ -198,8 +442,8 @@ impl RustWasm {
             uwriteln!(self.src, "pub mod {} {{", ns.to_snake_case());
             for (pkg, modules) in pkgs {
                 uwriteln!(self.src, "pub mod {} {{", pkg.to_snake_case());
-                for module in modules {
-                    uwriteln!(self.src, "{module}");
+                for (snake, module) in modules {
+                    uwriteln!(self.src, "{}", inline_module(snake, module));
                 }
                 uwriteln!(self.src, "}}");
             }
@@ -207,18 +451,466 @@ impl RustWasm {
         }
     }
 
+    /// Writes `modules` as files underneath `dir`, returning the module
+    /// declarations to place in `dir/mod.rs`.
+    fn emit_module_files(
+        &self,
+        modules: &BTreeMap<Option<PackageName>, Vec<(String, String)>>,
+        dir: &str,
+        files: &mut Files,
+    ) -> Source {
+        let mut decls = Source::default();
+        let mut map = BTreeMap::new();
+        for (pkg, modules) in modules {
+            match pkg {
+                Some(pkg) => {
+                    let prev = map
+                        .entry(pkg.namespace.to_snake_case())
+                        .or_insert(BTreeMap::new())
+                        .insert(pkg.name.to_snake_case(), modules);
+                    assert!(prev.is_none());
+                }
+                None => {
+                    for (snake, module) in modules {
+                        uwriteln!(decls, "#[allow(clippy::all)]\npub mod {snake};");
+                        self.push_file(files, &format!("{dir}/{snake}.rs"), module);
+                    }
+                }
+            }
+        }
+        for (ns, pkgs) in map {
+            uwriteln!(decls, "pub mod {ns};");
+            let mut ns_decls = Source::default();
+            for (pkg, modules) in pkgs {
+                uwriteln!(ns_decls, "pub mod {pkg};");
+                let mut pkg_decls = Source::default();
+                for (snake, module) in modules {
+                    uwriteln!(pkg_decls, "#[allow(clippy::all)]\npub mod {snake};");
+                    self.push_file(files, &format!("{dir}/{ns}/{pkg}/{snake}.rs"), module);
+                }
+                self.push_file(files, &format!("{dir}/{ns}/{pkg}/mod.rs"), &pkg_decls);
+            }
+            self.push_file(files, &format!("{dir}/{ns}/mod.rs"), &ns_decls);
+        }
+        decls
+    }
+
+    fn push_file(&self, files: &mut Files, name: &str, contents: &str) {
+        let mut src = Source::default();
+        wit_bindgen_core::generated_preamble(&mut src, env!("CARGO_PKG_VERSION"));
+        src.push_str(contents);
+        if self.opts.rustfmt {
+            rustfmt(&mut src);
+        }
+        files.push(name, src.as_bytes());
+    }
+
+    fn emit_export_macro(&mut self, module_name: &str) {
+        let shims = mem::take(&mut self.export_macro_shims).concat();
+        uwrite!(
+            self.src,
+            "
+                /// Exports the type `$ty` as the implementation of the traits
+                /// generated for this world's exported functions.
+                ///
+                /// Exported resources are implemented by the associated types of
+                /// `$ty`'s implementations of the interfaces' traits.
+                ///
+                /// This must be invoked in the module the bindings were generated
+                /// in, or `with_types_in` must be used to name the path to that
+                /// module, as in `export!(MyType, with_types_in path::to::bindings)`.
+                #[allow(unused_macros)]
+                macro_rules! __export_{module_name}_impl {{
+                    ($ty:path) => (self::export!($ty, with_types_in self););
+                    ($ty:path, with_types_in $($path_to_types:tt)*) => (
+            "
+        );
+        self.src.push_str(&shims);
+        self.src.push_str(
+            "
+                    );
+                }
+            ",
+        );
+        uwriteln!(
+            self.src,
+            "
+                #[allow(unused_imports)]
+                pub(crate) use __export_{module_name}_impl as export;
+            "
+        );
+    }
+
+    fn emit_reflection(&mut self, resolve: &Resolve, world: WorldId) {
+        let rt = self.runtime_path().to_string();
+        let world = &resolve.worlds[world];
+        let items = |items: &mut dyn Iterator<Item = (&WorldKey, &WorldItem)>| {
+            let mut src = String::new();
+            for (key, item) in items {
+                let item = match item {
+                    WorldItem::Interface(id) => {
+                        let iface = &resolve.interfaces[*id];
+                        let functions = iface
+                            .functions
+                            .values()
+                            .map(|func| reflect_function(resolve, &rt, func))
+                            .collect::<String>();
+                        let resources = iface
+                            .types
+                            .values()
+                            .filter_map(|id| reflect_resource(resolve, &rt, *id))
+                            .collect::<String>();
+                        format!(
+                            "Interface({rt}::reflect::Interface {{
+                                name: {:?},
+                                docs: {},
+                                functions: &[{functions}],
+                                resources: &[{resources}],
+                            }})",
+                            resolve.name_world_key(key),
+                            reflect_docs(&iface.docs),
+                        )
+                    }
+                    WorldItem::Function(func) => {
+                        format!("Function({})", reflect_function(resolve, &rt, func))
+                    }
+                    WorldItem::Type(id) => match reflect_resource(resolve, &rt, *id) {
+                        Some(resource) => format!("Resource({resource})"),
+                        None => continue,
+                    },
+                };
+                uwrite!(src, "{rt}::reflect::Item::{item},");
+            }
+            src
+        };
+        let imports = items(&mut world.imports.iter());
+        let exports = items(&mut world.exports.iter());
+        self.src.push_str(&format!(
+            "
+                /// A description of everything the `{name}` world imports and
+                /// exports.
+                pub const WORLD: {rt}::reflect::World = {rt}::reflect::World {{
+                    name: {name:?},
+                    docs: {docs},
+                    imports: &[{imports}],
+                    exports: &[{exports}],
+                }};
+            ",
+            name = world.name,
+            docs = reflect_docs(&world.docs),
+        ));
+    }
+
+    fn emit_dispatch(&mut self) {
+        let rt = self.runtime_path().to_string();
+        let shims = |shims: &[(String, String)]| {
+            shims
+                .iter()
+                .map(|(name, path)| format!("({name:?}, {path}),"))
+                .collect::<String>()
+        };
+        let imports = shims(&self.dispatch_imports);
+        let exports = shims(&self.dispatch_exports);
+        self.src.push_str(&format!(
+            "
+                /// Shims calling the imports and exports of this world with
+                /// dynamically typed values, by name.
+                pub const DISPATCH: {rt}::dynamic::Dispatch = {rt}::dynamic::Dispatch {{
+                    imports: &[{imports}],
+                    exports: &[{exports}],
+                }};
+            "
+        ));
+    }
+
     fn runtime_path(&self) -> &str {
         self.opts
             .runtime_path
             .as_deref()
             .unwrap_or("wit_bindgen::rt")
     }
+
+    /// Checks the options against `world` and analyzes its types, with the
+    /// types they remap, before anything is generated.
+    fn validate(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        // This generator has no representation for `future` and `stream`
+        // values yet, so reject them rather than panic partway through.
+        let mut live = LiveTypes::default();
+        live.add_world(resolve, world);
+        for ty in live.iter() {
+            let kind = match &resolve.types[ty].kind {
+                TypeDefKind::Future(_) => "future",
+                TypeDefKind::Stream(_) => "stream",
+                _ => continue,
+            };
+            bail!(
+                "world `{}` uses a `{kind}` type, which the Rust generator \
+                 doesn't support yet",
+                resolve.worlds[world].name
+            );
+        }
+        if self.opts.export_macro && !self.opts.exports.is_empty() {
+            let mut keys = self
+                .opts
+                .exports
+                .keys()
+                .map(|key| match key {
+                    ExportKey::World => "`world`".to_owned(),
+                    ExportKey::Name(name) => format!("`{name}`"),
+                })
+                .collect::<Vec<_>>();
+            keys.sort();
+            bail!(
+                "`exports` entries {} are unused with `export_macro`, which \
+                 registers implementations with `export!` instead",
+                keys.join(", ")
+            );
+        }
+        if self.opts.threads && self.opts.singleton {
+            bail!(
+                "the `singleton` option can't be used with `threads`, as the \
+                 instance can't be used by several export calls at once"
+            );
+        }
+        if self.opts.async_exports && self.opts.dynamic_exports {
+            bail!(
+                "the `async_exports` option can't be used with `dynamic_exports`, \
+                 as `Handler` implementations aren't async"
+            );
+        }
+        if self.opts.threads && self.opts.std_feature {
+            bail!(
+                "the `threads` option requires `std` for thread-local return \
+                 areas, so it can't be used with `std_feature`"
+            );
+        }
+        for (enabled, option) in [
+            (self.opts.dispatch, "dispatch"),
+            (self.opts.dynamic_exports, "dynamic_exports"),
+        ] {
+            for (other, other_option) in [
+                (self.opts.export_macro, "export_macro"),
+                (self.opts.singleton, "singleton"),
+            ] {
+                if enabled && other {
+                    bail!(
+                        "the `{option}` option can't be used with `{other_option}`, \
+                         as exports are then only implemented through their traits"
+                    );
+                }
+            }
+        }
+        if self.opts.dynamic_exports {
+            for (key, item) in resolve.worlds[world].exports.iter() {
+                let uses_resources = match item {
+                    WorldItem::Interface(id) => {
+                        let interface = &resolve.interfaces[*id];
+                        interface
+                            .types
+                            .values()
+                            .any(|ty| matches!(resolve.types[*ty].kind, TypeDefKind::Resource))
+                            || interface
+                                .functions
+                                .values()
+                                .any(|func| uses_resources(resolve, func))
+                    }
+                    WorldItem::Function(func) => uses_resources(resolve, func),
+                    WorldItem::Type(_) => false,
+                };
+                if uses_resources {
+                    bail!(
+                        "`dynamic_exports` can't implement the export `{}`, as \
+                         `Val` can't represent resources",
+                        resolve.name_world_key(key)
+                    );
+                }
+            }
+        }
+        self.resolve_remapped_types(resolve, world)?;
+        for ty in self.remapped.keys() {
+            self.types.opaque(*ty);
+        }
+        self.types.analyze(resolve);
+        if self.opts.serde {
+            let world = &resolve.worlds[world];
+            let types = world
+                .imports
+                .values()
+                .chain(world.exports.values())
+                .flat_map(|item| match item {
+                    WorldItem::Interface(id) => {
+                        resolve.interfaces[*id].types.values().copied().collect()
+                    }
+                    WorldItem::Type(id) => vec![*id],
+                    WorldItem::Function(_) => Vec::new(),
+                });
+            for id in types {
+                let ty = &resolve.types[id];
+                let compound = matches!(
+                    ty.kind,
+                    TypeDefKind::Record(_) | TypeDefKind::Variant(_) | TypeDefKind::Union(_)
+                );
+                if compound && self.types.get(id).has_resource {
+                    bail!(
+                        "the `serde` option can't serialize `{}`, as it contains resources",
+                        wit_type_name(resolve, &Type::Id(id))
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves the WIT types of `Opts::remap_types` to the types they remap.
+    fn resolve_remapped_types(&mut self, resolve: &Resolve, world: WorldId) -> Result<()> {
+        for (key, rust) in self.opts.remap_types.iter() {
+            let error = |reason: String| InvalidRemap {
+                key: key.clone(),
+                reason,
+            };
+            for ty in remapped_types(resolve, world, key).map_err(error)? {
+                if Types::default().type_info(resolve, &ty).has_resource {
+                    return Err(
+                        error(format!("can't remap `{key}` as it contains resources")).into(),
+                    );
+                }
+                if let Some(prev) = self.remapped.insert(ty, rust.clone()) {
+                    if prev != *rust {
+                        return Err(error(format!(
+                            "`{key}` is remapped to both `{prev}` and `{rust}`"
+                        ))
+                        .into());
+                    }
+                }
+                if let Type::Id(id) = ty {
+                    if let TypeDefKind::List(element) = &resolve.types[id].kind {
+                        self.remapped_lists.insert(*element, id);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the types remapped by `key`, an entry of `Opts::remap_types`.
+fn remapped_types(resolve: &Resolve, world: WorldId, key: &str) -> Result<Vec<Type>, String> {
+    let wit = key.split_whitespace().collect::<String>();
+    let mut tys = Vec::new();
+    if wit == "string" {
+        tys.push(Type::String);
+    } else if ["list<", "tuple<", "option<", "result<"]
+        .iter()
+        .any(|prefix| wit.starts_with(prefix))
+        || wit == "result"
+    {
+        for (id, ty) in resolve.types.iter() {
+            let name = match &ty.kind {
+                TypeDefKind::List(_)
+                | TypeDefKind::Tuple(_)
+                | TypeDefKind::Option(_)
+                | TypeDefKind::Result(_) => wit_kind_name(resolve, &ty.kind),
+                _ => continue,
+            };
+            if name.split_whitespace().collect::<String>() == wit {
+                tys.push(Type::Id(id));
+            }
+        }
+        if tys.is_empty() {
+            return Err(format!("remapped type `{key}` isn't used in any interface"));
+        }
+    } else {
+        let id = find_world_type(resolve, world, &wit)
+            .ok_or_else(|| format!("remapped type `{key}` isn't defined in the world"))?;
+        let id = dealias(resolve, id);
+        match &resolve.types[id].kind {
+            TypeDefKind::Type(ty) => {
+                return Err(format!(
+                    "can't remap `{key}`, an alias of `{}`",
+                    wit_type_name(resolve, ty)
+                ))
+            }
+            kind @ TypeDefKind::List(_) => {
+                return Err(format!(
+                    "named lists can't be remapped, so remap `{}` instead of `{key}`",
+                    wit_kind_name(resolve, kind)
+                ))
+            }
+            _ => tys.push(Type::Id(id)),
+        }
+    }
+    Ok(tys)
+}
+
+/// The generator returned by `Opts::build`, which validates the options
+/// against the world before generating it with `RustWasm`, as the
+/// `WorldGenerator` hooks have no way to fail up front.
+struct Validated(RustWasm);
+
+impl WorldGenerator for Validated {
+    fn generate(&mut self, resolve: &Resolve, id: WorldId, files: &mut Files) -> Result<()> {
+        self.0.validate(resolve, id)?;
+        self.0.generate(resolve, id, files)
+    }
+
+    fn import_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        iface: InterfaceId,
+        files: &mut Files,
+    ) {
+        self.0.import_interface(resolve, name, iface, files)
+    }
+
+    fn export_interface(
+        &mut self,
+        resolve: &Resolve,
+        name: &WorldKey,
+        iface: InterfaceId,
+        files: &mut Files,
+    ) -> Result<()> {
+        self.0.export_interface(resolve, name, iface, files)
+    }
+
+    fn import_funcs(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        funcs: &[(&str, &Function)],
+        files: &mut Files,
+    ) {
+        self.0.import_funcs(resolve, world, funcs, files)
+    }
+
+    fn export_funcs(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        funcs: &[(&str, &Function)],
+        files: &mut Files,
+    ) -> Result<()> {
+        self.0.export_funcs(resolve, world, funcs, files)
+    }
+
+    fn import_types(
+        &mut self,
+        resolve: &Resolve,
+        world: WorldId,
+        types: &[(&str, TypeId)],
+        files: &mut Files,
+    ) {
+        self.0.import_types(resolve, world, types, files)
+    }
+
+    fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) {
+        self.0.finish(resolve, world, files)
+    }
 }
 
 impl WorldGenerator for RustWasm {
-    fn preprocess(&mut self, resolve: &Resolve, _world: WorldId) {
+    fn preprocess(&mut self, _resolve: &Resolve, _world: WorldId) {
         wit_bindgen_core::generated_preamble(&mut self.src, env!("CARGO_PKG_VERSION"));
-        self.types.analyze(resolve);
     }
 
     fn import_interface(
@@ -291,14 +983,16 @@ impl WorldGenerator for RustWasm {
             .get(&ExportKey::Name(path.clone()))
             .cloned()
             .or_else(|| self.opts.stubs.then(|| "Stub".to_owned()))
-            .ok_or_else(|| format!("export parameter required for `{path}`"));
+            .ok_or_else(|| MissingExport {
+                key: ExportKey::Name(path.clone()),
+            });
         let mut gen = self.interface(Identifier::Interface(id, name), None, resolve, false);
         let (snake, path_to_root, pkg) = gen.start_append_submodule(name);
         gen.types(id);
         gen.generate_exports(
             &inner_name.to_upper_camel_case(),
             Some(&path),
-            impl_name.as_deref().map_err(|s| s.as_str()),
+            impl_name.as_deref(),
             Some(name),
             resolve.interfaces[id].functions.values(),
         )?;
@@ -320,13 +1014,15 @@ impl WorldGenerator for RustWasm {
             .get(&ExportKey::World)
             .cloned()
             .or_else(|| self.opts.stubs.then(|| "Stub".to_owned()))
-            .ok_or_else(|| format!("export parameter required for `world`"));
+            .ok_or(MissingExport {
+                key: ExportKey::World,
+            });
         let trait_name = world_name.to_upper_camel_case();
         let mut gen = self.interface(Identifier::World(world), None, resolve, false);
         gen.generate_exports(
             &trait_name,
             None,
-            impl_name.as_deref().map_err(|s| s.as_str()),
+            impl_name.as_deref(),
             None,
             funcs.iter().map(|f| f.1),
         )?;
@@ -361,25 +1057,75 @@ impl WorldGenerator for RustWasm {
 
     fn finish(&mut self, resolve: &Resolve, world: WorldId, files: &mut Files) {
         let name = &resolve.worlds[world].name;
+        let module_name = name.to_snake_case();
         let imports = mem::take(&mut self.import_modules);
-        self.emit_modules(&imports);
         let exports = mem::take(&mut self.export_modules);
-        if !exports.is_empty() {
-            self.src.push_str("pub mod exports {\n");
-            self.emit_modules(&exports);
-            self.src.push_str("}\n");
+        if self.opts.split_modules {
+            let decls = self.emit_module_files(&imports, &module_name, files);
+            self.src.push_str(&decls);
+            if !exports.is_empty() {
+                let dir = format!("{module_name}/exports");
+                let decls = self.emit_module_files(&exports, &dir, files);
+                self.push_file(files, &format!("{dir}/mod.rs"), &decls);
+                self.src.push_str("pub mod exports;\n");
+            }
+        } else {
+            self.emit_modules(&imports);
+            if !exports.is_empty() {
+                self.src.push_str("pub mod exports {\n");
+                self.emit_modules(&exports);
+                self.src.push_str("}\n");
+            }
         }
 
-        self.src.push_str("\n#[cfg(target_arch = \"wasm32\")]\n");
-
-        // The custom section name here must start with "component-type" but
-        // otherwise is attempted to be unique here to ensure that this doesn't get
-        // concatenated to other custom sections by LLD by accident since LLD will
-        // concatenate custom sections of the same name.
-        self.src
-            .push_str(&format!("#[link_section = \"component-type:{}\"]\n", name,));
+        if self.opts.export_macro {
+            self.emit_export_macro(&module_name);
+        }
 
-        let mut producers = wasm_metadata::Producers::empty();
+        if !self.remapped.is_empty() {
+            self.src.push_str(
+                "
+                    /// Conversions between a Rust type used in place of a WIT type,
+                    /// with `remap_types`, and `T`, the type which would have been
+                    /// generated for that WIT type.
+                    pub trait WitRemap<T> {
+                        fn from_wit(wit: T) -> Self;
+                        fn to_wit(&self) -> T;
+                    }
+                ",
+            );
+        }
+
+        if self.opts.reflection {
+            self.emit_reflection(resolve, world);
+        }
+
+        if self.opts.dispatch {
+            self.emit_dispatch();
+        }
+
+        let rt = self.runtime_path().to_owned();
+        for (index, impl_name) in self.singletons.iter().enumerate() {
+            uwriteln!(
+                self.src,
+                "
+                    #[doc(hidden)]
+                    static __SINGLETON_{index}: {rt}::singleton::Instance<{impl_name}> =
+                        {rt}::singleton::Instance::new();
+                ",
+            );
+        }
+
+        self.src.push_str("\n#[cfg(target_arch = \"wasm32\")]\n");
+
+        // The custom section name here must start with "component-type" but
+        // otherwise is attempted to be unique here to ensure that this doesn't get
+        // concatenated to other custom sections by LLD by accident since LLD will
+        // concatenate custom sections of the same name.
+        self.src
+            .push_str(&format!("#[link_section = \"component-type:{}\"]\n", name,));
+
+        let mut producers = wasm_metadata::Producers::empty();
         producers.add(
             "processed-by",
             env!("CARGO_PKG_NAME"),
@@ -389,7 +1135,7 @@ impl WorldGenerator for RustWasm {
         let component_type = wit_component::metadata::encode(
             resolve,
             world,
-            wit_component::StringEncoding::UTF8,
+            self.opts.string_encoding,
             Some(&producers),
         )
         .unwrap();
@@ -411,7 +1157,12 @@ impl WorldGenerator for RustWasm {
         );
 
         if self.opts.stubs {
-            self.src.push_str("\n#[derive(Debug)]\npub struct Stub;\n");
+            if self.opts.singleton {
+                self.src
+                    .push_str("\n#[derive(Debug, Default)]\npub struct Stub;\n");
+            } else {
+                self.src.push_str("\n#[derive(Debug)]\npub struct Stub;\n");
+            }
             let world_id = world;
             let world = &resolve.worlds[world];
             let mut funcs = Vec::new();
@@ -431,12 +1182,28 @@ impl WorldGenerator for RustWasm {
                         funcs.push(func);
                     }
                     WorldItem::Interface(id) => {
-                        for (resource, funcs) in
-                            group_by_resource(resolve.interfaces[*id].functions.values())
-                        {
+                        let interface = &resolve.interfaces[*id];
+                        let mut by_resource = group_by_resource(interface.functions.values());
+                        // `Stub` implements every resource with `export_macro`.
+                        let mut resources = Vec::new();
+                        if self.opts.export_macro {
+                            for ty in interface.types.values() {
+                                if let TypeDefKind::Resource = &resolve.types[*ty].kind {
+                                    by_resource.entry(Some(*ty)).or_default();
+                                    by_resource.entry(None).or_default();
+                                    resources.push(*ty);
+                                }
+                            }
+                        }
+                        for (resource, funcs) in by_resource {
                             let mut gen =
                                 self.interface(Identifier::World(world_id), None, resolve, false);
-                            gen.generate_stub(resource, pkg, name, true, &funcs);
+                            let resources = if resource.is_none() {
+                                &resources[..]
+                            } else {
+                                &[]
+                            };
+                            gen.generate_stub(resource, pkg, name, true, resources, &funcs);
                             let stub = gen.finish();
                             self.src.push_str(&stub);
                         }
@@ -447,42 +1214,76 @@ impl WorldGenerator for RustWasm {
 
             for (resource, funcs) in group_by_resource(funcs.into_iter()) {
                 let mut gen = self.interface(Identifier::World(world_id), None, resolve, false);
-                gen.generate_stub(resource, None, &world.name, false, &funcs);
+                gen.generate_stub(resource, None, &world.name, false, &[], &funcs);
                 let stub = gen.finish();
                 self.src.push_str(&stub);
             }
+
+            if self.opts.dynamic_exports {
+                let rt = self.runtime_path().to_string();
+                uwriteln!(
+                    self.src,
+                    "impl {rt}::dynamic::Handler for Stub {{
+                        fn call(_: &str, _: {rt}::vec::Vec<{rt}::Val>) -> {rt}::vec::Vec<{rt}::Val> {{
+                            unreachable!()
+                        }}
+                    }}"
+                );
+            }
+
+            if self.opts.export_macro {
+                self.src.push_str("export!(Stub);\n");
+            }
         }
 
         let mut src = mem::take(&mut self.src);
         if self.opts.rustfmt {
-            let mut child = Command::new("rustfmt")
-                .arg("--edition=2018")
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()
-                .expect("failed to spawn `rustfmt`");
-            child
-                .stdin
-                .take()
-                .unwrap()
-                .write_all(src.as_bytes())
-                .unwrap();
-            src.as_mut_string().truncate(0);
-            child
-                .stdout
-                .take()
-                .unwrap()
-                .read_to_string(src.as_mut_string())
-                .unwrap();
-            let status = child.wait().unwrap();
-            assert!(status.success());
+            rustfmt(&mut src);
         }
 
-        let module_name = name.to_snake_case();
-        files.push(&format!("{module_name}.rs"), src.as_bytes());
+        if self.opts.split_modules {
+            files.push(&format!("{module_name}/mod.rs"), src.as_bytes());
+        } else {
+            files.push(&format!("{module_name}.rs"), src.as_bytes());
+        }
     }
 }
 
+fn inline_module(snake: &str, module: &str) -> String {
+    format!(
+        "
+            #[allow(clippy::all)]
+            pub mod {snake} {{
+                {module}
+            }}
+        "
+    )
+}
+
+fn rustfmt(src: &mut Source) {
+    let mut child = Command::new("rustfmt")
+        .arg("--edition=2018")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn `rustfmt`");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(src.as_bytes())
+        .unwrap();
+    src.as_mut_string().truncate(0);
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(src.as_mut_string())
+        .unwrap();
+    let status = child.wait().unwrap();
+    assert!(status.success());
+}
+
 enum Identifier<'a> {
     World(WorldId),
     Interface(InterfaceId, &'a WorldKey),
@@ -498,6 +1299,141 @@ struct InterfaceGenerator<'a> {
     resolve: &'a Resolve,
     return_pointer_area_size: usize,
     return_pointer_area_align: usize,
+    helpers_src: Source,
+}
+
+/// The module helpers are defined in: an interface imported or exported, or
+/// the root for the world.
+type HelperModule = Option<(InterfaceId, bool)>;
+
+/// The kinds of per-type helper functions emitted when
+/// `Opts::outline_helpers` is enabled.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+enum HelperKind {
+    /// Lowers a borrowed list element into memory, used for import arguments.
+    LowerBorrowed,
+    /// Lowers an owned value into memory, used for list elements and results
+    /// of exports.
+    Lower,
+    /// Lifts a value out of memory, used for list elements and results of
+    /// imports.
+    Lift,
+    /// Deallocates any memory owned by a value in a post-return.
+    Dealloc,
+    /// Lowers a borrowed value to its flat representation, used for import
+    /// arguments and single-valued export results.
+    LowerFlat,
+    /// Lifts a value from its flat representation, used for export arguments
+    /// and single-valued import results.
+    LiftFlat,
+}
+
+/// A helper emitted with `Opts::outline_helpers`.
+#[derive(Clone)]
+struct Helper {
+    name: String,
+    /// Whether the helper takes the caller's `cleanup_list` as its last
+    /// argument.
+    cleanup_list: bool,
+}
+
+impl Helper {
+    fn call(&self, args: &str) -> String {
+        if self.cleanup_list {
+            format!("{}({args}, &mut cleanup_list)", self.name)
+        } else {
+            format!("{}({args})", self.name)
+        }
+    }
+}
+
+/// The function whose glue a `FunctionBindgen` generates for `Val`s, see
+/// `Opts::dispatch` and `Opts::dynamic_exports`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum DynamicCall {
+    /// The dispatch shim of an import, which borrows the arguments it lowers.
+    Import,
+    /// The dispatch shim of an export, calling the copy of its glue `abi`,
+    /// which takes ownership of the arguments it lowers.
+    Export,
+    /// The glue of an export implemented by a `Handler`.
+    Handler,
+}
+
+/// The state of a `FunctionBindgen` lifting and lowering `Val`s.
+struct Dynamic {
+    call: DynamicCall,
+    /// The name of the function, as in `DISPATCH`.
+    func: String,
+    /// Whether the next return pointer is instead the parameters of `abi`,
+    /// which it deallocates.
+    params_area: bool,
+}
+
+/// How `FunctionBindgen::emit` continues after `emit_dynamic`.
+enum DynamicEmit<'i> {
+    /// The instruction was emitted for `Val`s.
+    Emitted,
+    /// The instruction is emitted as for the generated types, or as the given
+    /// instruction instead.
+    Typed(Option<Instruction<'i>>),
+    /// The value lifted as for the generated types is wrapped in this case of
+    /// `Val`.
+    Lifted(&'static str),
+}
+
+/// Where values are lifted and lowered by outlined helpers in a call.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum OutlineSite {
+    Import,
+    Export,
+    PostReturn,
+}
+
+/// A value of a call which is lifted or lowered by a helper.
+struct Outlined {
+    id: TypeId,
+    kind: HelperKind,
+    helper: Helper,
+}
+
+/// Code generated so far, with its pending cleanup and whether it adds to
+/// `cleanup_list`, set aside while generating something else.
+type SavedSource = (Source, Vec<(String, String)>, bool);
+
+/// The values of a call lifted and lowered by helpers with
+/// `Opts::outline_helpers`.
+///
+/// The code `resolve.call` generates for these values is discarded, and the
+/// operands it would have produced are replaced with the helpers' results.
+#[derive(Default)]
+struct CallOutlining {
+    site: Option<OutlineSite>,
+    /// The outlined parameters, with the number of flat values of every
+    /// parameter.
+    params: Vec<(Option<Outlined>, usize)>,
+    result: Option<Outlined>,
+    /// The source and cleanup being generated before discarding began.
+    discarded: Option<SavedSource>,
+    /// The variables holding outlined parameters, by index.
+    values: HashMap<usize, String>,
+    /// The export parameter being lifted, with its flat arguments.
+    pending: Option<(usize, Vec<String>)>,
+    /// The operand passed to the helper of the result.
+    result_operand: Option<String>,
+}
+
+impl HelperKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            HelperKind::LowerBorrowed => "lower_borrowed",
+            HelperKind::Lower => "lower",
+            HelperKind::Lift => "lift",
+            HelperKind::Dealloc => "dealloc",
+            HelperKind::LowerFlat => "lower_flat",
+            HelperKind::LiftFlat => "lift_flat",
+        }
+    }
 }
 
 impl InterfaceGenerator<'_> {
@@ -505,7 +1441,7 @@ impl InterfaceGenerator<'_> {
         &mut self,
         trait_name: &str,
         path: Option<&str>,
-        impl_name: Result<&str, &str>,
+        impl_name: Result<&str, &MissingExport>,
         interface_name: Option<&WorldKey>,
         funcs: impl Iterator<Item = &'a Function>,
     ) -> Result<()> {
@@ -532,6 +1468,14 @@ impl InterfaceGenerator<'_> {
             }
         }
 
+        // With `export_macro` the implementations of resources are named by
+        // associated types of the interface's trait.
+        let resources = by_resource.keys().flatten().copied().collect::<Vec<_>>();
+        if self.gen.opts.export_macro && !resources.is_empty() {
+            by_resource.entry(None).or_default();
+        }
+        let interface_trait = trait_name;
+
         for (resource, funcs) in by_resource {
             let trait_name = if let Some(ty) = resource {
                 self.resolve.types[ty]
@@ -540,21 +1484,52 @@ impl InterfaceGenerator<'_> {
                     .unwrap()
                     .to_upper_camel_case()
             } else {
-                trait_name.to_owned()
+                interface_trait.to_owned()
             };
             let mut saw_export = false;
-            uwriteln!(self.src, "pub trait {trait_name} {{");
+            let singleton = self.gen.opts.singleton && resource.is_none();
+            if singleton && self.gen.opts.export_macro {
+                bail!("the `singleton` option is not supported with `export_macro`");
+            }
+            let supertraits = if singleton { ": Default" } else { "" };
+            if self.gen.opts.async_exports {
+                // The futures are only polled by `block_on`, so their auto
+                // traits don't matter.
+                self.src.push_str("#[allow(async_fn_in_trait)]\n");
+            }
+            uwriteln!(self.src, "pub trait {trait_name}{supertraits} {{");
+            if self.gen.opts.export_macro && resource.is_none() {
+                for ty in resources.iter() {
+                    let name = self.resolve.types[*ty].name.as_deref().unwrap();
+                    let camel = name.to_upper_camel_case();
+                    uwriteln!(
+                        self.src,
+                        "
+                            /// The type implementing exported `{name}` resources.
+                            type {camel}: {camel} + 'static;
+                        "
+                    );
+                }
+            }
             for &func in &funcs {
                 if self.gen.skip.contains(&func.name) {
                     continue;
                 }
                 saw_export = true;
+                if self.gen.opts.error_conversion {
+                    self.print_error_type(func, false);
+                }
                 let mut sig = FnSig::default();
                 sig.use_item_name = true;
                 sig.private = true;
+                sig.borrow_canonical_params = self.gen.opts.borrowed_export_params;
+                sig.convert_errors = self.gen.opts.error_conversion;
+                sig.async_ = self.gen.opts.async_exports;
                 if let FunctionKind::Method(_) = &func.kind {
-                    sig.self_arg = Some("&self".into());
+                    sig.self_arg = Some(self.export_self_arg().into());
                     sig.self_is_first_param = true;
+                } else if singleton {
+                    sig.self_arg = Some("&mut self".into());
                 }
                 self.print_signature(func, TypeMode::Owned, &sig);
                 self.src.push_str(";\n");
@@ -572,7 +1547,9 @@ impl InterfaceGenerator<'_> {
                     }
                     path_to_root.push_str("super::");
                 }
-                if let Some(ty) = resource {
+                if let (Some(ty), true) = (resource, self.gen.opts.export_macro) {
+                    self.print_resource_rep(ty, interface_trait);
+                } else if let Some(ty) = resource {
                     let name = self.resolve.types[ty].name.as_deref().unwrap();
                     let path = if let Some(path) = path {
                         format!("{path}/{name}")
@@ -586,26 +1563,67 @@ impl InterfaceGenerator<'_> {
                         .get(&ExportKey::Name(path.clone()))
                         .cloned()
                         .or_else(|| self.gen.opts.stubs.then(|| "Stub".to_owned()))
-                        .ok_or_else(|| anyhow!("export parameter required for `{path}`"))?;
+                        .ok_or_else(|| MissingExport {
+                            key: ExportKey::Name(path.clone()),
+                        })?;
 
                     uwriteln!(
                         self.src,
                         "pub use {path_to_root}{impl_name} as Rep{trait_name};"
                     );
-                } else {
-                    let impl_name = impl_name.map_err(|s| anyhow!("{s}"))?;
+                } else if !self.gen.opts.export_macro {
+                    let impl_name = impl_name.map_err(Clone::clone)?;
                     uwriteln!(
                         self.src,
                         "use {path_to_root}{impl_name} as {trait_name}Impl;"
                     );
                 }
-                if saw_export {
+                if self.gen.opts.export_macro {
+                    for &func in &funcs {
+                        self.generate_guest_export(
+                            func,
+                            interface_name,
+                            &trait_name,
+                            interface_trait,
+                        );
+                    }
+                } else if saw_export {
                     self.src.push_str("const _: () = {\n");
+                    if singleton {
+                        let impl_name = impl_name.map_err(Clone::clone)?;
+                        let singletons = &mut self.gen.singletons;
+                        let index = match singletons.iter().position(|s| s == impl_name) {
+                            Some(index) => index,
+                            None => {
+                                singletons.push(impl_name.to_owned());
+                                singletons.len() - 1
+                            }
+                        };
+                        uwriteln!(
+                            self.src,
+                            "
+                                fn __instance() -> {rt}::singleton::InstanceMut<{trait_name}Impl> {{
+                                    {path_to_root}__SINGLETON_{index}.borrow_mut()
+                                }}
+                            ",
+                            rt = self.gen.runtime_path(),
+                        );
+                    }
                     for &func in &funcs {
-                        self.generate_guest_export(func, interface_name, &trait_name);
+                        self.generate_guest_export(
+                            func,
+                            interface_name,
+                            &trait_name,
+                            interface_trait,
+                        );
                     }
                     self.src.push_str("};\n");
                 }
+                if resource.is_none() {
+                    for &func in &funcs {
+                        self.generate_dispatch_shim(func, Some(&trait_name));
+                    }
+                }
 
                 if let Some(ty) = resource {
                     self.finish_resource_export(ty);
@@ -646,6 +1664,7 @@ impl InterfaceGenerator<'_> {
                 let name = self.resolve.types[resource].name.as_deref().unwrap();
 
                 let camel = name.to_upper_camel_case();
+                let rt = self.gen.runtime_path();
 
                 uwriteln!(
                     self.src,
@@ -662,6 +1681,13 @@ impl InterfaceGenerator<'_> {
                                      #[cfg(not(target_arch = "wasm32"))]
                                      unsafe fn wit_import(_n: i32) {{ unreachable!() }}
 
+                                     {rt}::handles::release(
+                                         "{wasm_import_module}#{name}",
+                                         {rt}::handles::Kind::from_owned(self.owned),
+                                         self.handle,
+                                         "drop",
+                                     );
+
                                      if self.owned {{
                                          #[cfg(target_arch = "wasm32")]
                                          #[link(wasm_import_module = "{wasm_import_module}")]
@@ -688,12 +1714,26 @@ impl InterfaceGenerator<'_> {
                         impl {camel} {{
                             #[doc(hidden)]
                             pub unsafe fn from_handle(handle: i32, owned: bool) -> Self {{
-                                Self {{ handle, owned }}
+                                let this = Self {{ handle, owned }};
+                                {rt}::handles::acquire(
+                                    "{wasm_import_module}#{name}",
+                                    {rt}::handles::Kind::from_owned(this.owned),
+                                    handle,
+                                    "from_handle",
+                                );
+                                this
                             }}
 
                             #[doc(hidden)]
                             pub fn into_handle(self) -> i32 {{
-                                ::core::mem::ManuallyDrop::new(self).handle
+                                let this = ::core::mem::ManuallyDrop::new(self);
+                                {rt}::handles::release(
+                                    "{wasm_import_module}#{name}",
+                                    {rt}::handles::Kind::from_owned(this.owned),
+                                    this.handle,
+                                    "into_handle",
+                                );
+                                this.handle
                             }}
                     "#
                 );
@@ -717,22 +1757,395 @@ impl InterfaceGenerator<'_> {
 
                     #[repr(align({align}))]
                     struct _RetArea([u8; {size}]);
-                    static mut _RET_AREA: _RetArea = _RetArea([0; {size}]);
                 ",
                 rt = self.gen.runtime_path(),
                 align = self.return_pointer_area_align,
                 size = self.return_pointer_area_size,
             );
+            if self.gen.opts.threads {
+                // Each thread gets its own return area so concurrent exports
+                // don't clobber each other's results.
+                uwrite!(
+                    self.src,
+                    "
+                        ::std::thread_local! {{
+                            static _RET_AREA: ::core::cell::UnsafeCell<_RetArea> =
+                                ::core::cell::UnsafeCell::new(_RetArea([0; {size}]));
+                        }}
+                    ",
+                    size = self.return_pointer_area_size,
+                );
+            } else {
+                uwrite!(
+                    self.src,
+                    "
+                        static mut _RET_AREA: _RetArea = _RetArea([0; {size}]);
+                    ",
+                    size = self.return_pointer_area_size,
+                );
+            }
         }
+        self.src.push_str(&mem::take(&mut self.helpers_src));
 
         mem::take(&mut self.src).into()
     }
 
+    /// The module helpers emitted by this generator are defined in.
+    fn helper_module(&self) -> HelperModule {
+        match self.identifier {
+            Identifier::Interface(id, _) => Some((id, self.in_import)),
+            Identifier::World(_) => None,
+        }
+    }
+
+    /// Returns the named type whose helper of `kind` lifts, lowers, or
+    /// deallocates `ty`, or `None` if `ty` shouldn't be outlined.
+    fn outlined_type(&self, ty: &Type, kind: HelperKind) -> Option<TypeId> {
+        if !self.gen.opts.outline_helpers || self.has_remapped_type(ty) {
+            return None;
+        }
+        let id = match ty {
+            Type::Id(id) => dealias(self.resolve, *id),
+            _ => return None,
+        };
+        let ty = &self.resolve.types[id];
+        ty.name.as_ref()?;
+        if let TypeDefKind::Type(_) | TypeDefKind::Resource | TypeDefKind::Handle(_) = ty.kind {
+            return None;
+        }
+        let info = self.info(id);
+        if info.has_resource || info.has_borrow_handle {
+            return None;
+        }
+        // Borrowed types are only spelled consistently when they don't have
+        // distinct borrowing definitions.
+        if let HelperKind::LowerBorrowed | HelperKind::LowerFlat = kind {
+            if info.has_list && !matches!(self.gen.opts.ownership, Ownership::Owning) {
+                return None;
+            }
+        }
+        Some(id)
+    }
+
+    /// Returns a call to the helper function of `kind` for the list element
+    /// type `ty` to use in place of `body`, emitting the helper with `body` if
+    /// it doesn't exist yet, along with whether it's passed `cleanup_list`.
+    ///
+    /// `cleanup_list` is whether `body` adds to the caller's cleanup list.
+    /// Returns `None` if `ty` shouldn't be outlined, in which case `body`
+    /// should be inlined at the use site as usual.
+    fn list_element_helper(
+        &mut self,
+        ty: &Type,
+        kind: HelperKind,
+        body: &str,
+        cleanup_list: bool,
+    ) -> Option<(String, bool)> {
+        if body == "()" {
+            return None;
+        }
+        let id = self.outlined_type(ty, kind)?;
+        let args = match kind {
+            HelperKind::LowerBorrowed | HelperKind::Lower => "e, base",
+            _ => "base",
+        };
+        let helper = match self.gen.helpers.get(&(self.helper_module(), id, kind)) {
+            Some(helper) => helper.clone(),
+            None => {
+                let ty = self.helper_type(id);
+                let (params, results) = match kind {
+                    HelperKind::LowerBorrowed => (format!("e: &{ty}, base: i32"), String::new()),
+                    HelperKind::Lower => (format!("e: {ty}, base: i32"), String::new()),
+                    HelperKind::Lift => ("base: i32".to_owned(), format!(" -> {ty}")),
+                    _ => ("base: i32".to_owned(), String::new()),
+                };
+                let cleanup_list = kind == HelperKind::LowerBorrowed && cleanup_list;
+                self.define_helper(id, kind, cleanup_list, &params, &results, body)
+            }
+        };
+        let call = helper.call(args);
+        Some(match kind {
+            HelperKind::Lift => (call, helper.cleanup_list),
+            _ => (format!("{call};\n"), helper.cleanup_list),
+        })
+    }
+
+    /// Returns the helper of `kind` for the value of the named type `id`,
+    /// emitting it if it doesn't exist yet.
+    ///
+    /// The helper's body is generated by lifting or lowering a parameter or
+    /// result of a synthesized function, so it's the same code as would be
+    /// inlined in a call. Returns `None` if `id` isn't outlined this way.
+    fn helper(&mut self, id: TypeId, kind: HelperKind) -> Option<Helper> {
+        if let Some(helper) = self.gen.helpers.get(&(self.helper_module(), id, kind)) {
+            return Some(helper.clone());
+        }
+        let flat = flat_types(self.resolve, AbiVariant::GuestExport, &Type::Id(id));
+        let mut func = Function {
+            docs: Docs::default(),
+            name: String::new(),
+            kind: FunctionKind::Freestanding,
+            params: Vec::new(),
+            results: Results::Named(Vec::new()),
+        };
+        let ty = self.helper_type(id);
+        let flat_results = match flat.len() {
+            1 => wasm_type(flat[0]).to_owned(),
+            _ => format!(
+                "({})",
+                flat.iter()
+                    .map(|t| wasm_type(*t))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let (params, args, results) = match kind {
+            // Values are flattened into at most this many arguments.
+            HelperKind::LowerFlat | HelperKind::LiftFlat if flat.is_empty() || flat.len() > 16 => {
+                return None
+            }
+            HelperKind::LowerFlat => {
+                func.params.push(("e".to_owned(), Type::Id(id)));
+                (
+                    format!("e: &{ty}"),
+                    vec!["e".to_owned()],
+                    format!(" -> {flat_results}"),
+                )
+            }
+            HelperKind::LiftFlat => {
+                func.params.push(("e".to_owned(), Type::Id(id)));
+                let args = (0..flat.len())
+                    .map(|i| format!("arg{i}"))
+                    .collect::<Vec<_>>();
+                let params = args
+                    .iter()
+                    .zip(&flat)
+                    .map(|(arg, ty)| format!("{arg}: {}", wasm_type(*ty)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (params, args, format!(" -> {ty}"))
+            }
+            // Values stored in memory are only returned through memory, and
+            // written to the return area, if they're not a single value.
+            HelperKind::Lift | HelperKind::Lower | HelperKind::Dealloc if flat.len() <= 1 => {
+                return None
+            }
+            HelperKind::Lift => {
+                func.results = Results::Anon(Type::Id(id));
+                ("base: i32".to_owned(), Vec::new(), format!(" -> {ty}"))
+            }
+            HelperKind::Lower => {
+                func.results = Results::Anon(Type::Id(id));
+                (format!("e: {ty}, base: i32"), Vec::new(), String::new())
+            }
+            HelperKind::Dealloc => {
+                func.results = Results::Anon(Type::Id(id));
+                if !self.resolve.guest_export_needs_post_return(&func) {
+                    return None;
+                }
+                (
+                    "base: i32".to_owned(),
+                    vec!["base".to_owned()],
+                    String::new(),
+                )
+            }
+            HelperKind::LowerBorrowed => return None,
+        };
+
+        let mut f = FunctionBindgen::new(self, args, None);
+        f.helper = Some(kind);
+        match kind {
+            HelperKind::LowerFlat | HelperKind::Lift => f.gen.resolve.call(
+                AbiVariant::GuestImport,
+                LiftLower::LowerArgsLiftResults,
+                &func,
+                &mut f,
+            ),
+            HelperKind::LiftFlat | HelperKind::Lower => f.gen.resolve.call(
+                AbiVariant::GuestExport,
+                LiftLower::LiftArgsLowerResults,
+                &func,
+                &mut f,
+            ),
+            _ => f.gen.resolve.post_return(&func, &mut f),
+        }
+        let FunctionBindgen {
+            needs_cleanup_list,
+            src,
+            ..
+        } = f;
+        Some(self.define_helper(id, kind, needs_cleanup_list, &params, &results, &src))
+    }
+
+    /// The owned type of the values of helpers for `id`.
+    fn helper_type(&mut self, id: TypeId) -> String {
+        let prev = mem::take(&mut self.src);
+        self.print_ty(&Type::Id(id), TypeMode::Owned);
+        String::from(mem::replace(&mut self.src, prev))
+    }
+
+    /// Emits the helper of `kind` for `id`, naming it uniquely within its
+    /// module.
+    fn define_helper(
+        &mut self,
+        id: TypeId,
+        kind: HelperKind,
+        cleanup_list: bool,
+        params: &str,
+        results: &str,
+        body: &str,
+    ) -> Helper {
+        let module = self.helper_module();
+        let names = self.gen.helper_names.entry(module).or_default();
+        let ty_name = self.resolve.types[id].name.as_deref().unwrap();
+        let base = format!("__{}_{}", kind.prefix(), ty_name.to_snake_case());
+        let mut name = base.clone();
+        let mut n = 1;
+        while !names.insert(name.clone()) {
+            name = format!("{base}{n}");
+            n += 1;
+        }
+
+        let rt = self.gen.runtime_path();
+        let cleanup_param = if cleanup_list {
+            format!(", cleanup_list: &mut {rt}::vec::Vec<(*mut u8, {rt}::alloc::Layout)>")
+        } else {
+            String::new()
+        };
+        uwrite!(
+            self.helpers_src,
+            "
+                #[allow(clippy::all)]
+                #[inline(never)]
+                unsafe fn {name}({params}{cleanup_param}){results} {{
+                    #[allow(unused_imports)]
+                    use {rt}::{{alloc, vec::Vec, string::String}};
+
+                    {body}
+                }}
+            ",
+        );
+        let helper = Helper { name, cleanup_list };
+        self.gen.helpers.insert((module, id, kind), helper.clone());
+        helper
+    }
+
+    /// Returns the values of a call of `func` at `site` to lift and lower with
+    /// helpers, emitting the helpers.
+    fn call_outlining(&mut self, func: &Function, site: OutlineSite) -> CallOutlining {
+        // Exports implemented dynamically lift and lower `Val`s instead.
+        if !self.gen.opts.outline_helpers
+            || (site == OutlineSite::Export && self.gen.opts.dynamic_exports)
+        {
+            return CallOutlining::default();
+        }
+        let variant = match site {
+            OutlineSite::Import => AbiVariant::GuestImport,
+            OutlineSite::Export | OutlineSite::PostReturn => AbiVariant::GuestExport,
+        };
+        let sig = self.resolve.wasm_signature(variant, func);
+        let mut outlining = CallOutlining {
+            site: Some(site),
+            ..Default::default()
+        };
+        let outline = |gen: &mut Self, ty: &Type, kind: HelperKind| {
+            let id = gen.outlined_type(ty, kind)?;
+            let helper = gen.helper(id, kind)?;
+            Some(Outlined { id, kind, helper })
+        };
+        if site != OutlineSite::PostReturn && !sig.indirect_params {
+            let kind = match site {
+                OutlineSite::Import => HelperKind::LowerFlat,
+                _ => HelperKind::LiftFlat,
+            };
+            for (_, ty) in func.params.iter() {
+                let flat = flat_types(self.resolve, variant, ty);
+                let outlined = outline(self, ty, kind);
+                outlining.params.push((outlined, flat.len()));
+            }
+        }
+        if let Results::Anon(ty) = &func.results {
+            let kind = match (site, sig.retptr) {
+                (OutlineSite::Import, true) => HelperKind::Lift,
+                (OutlineSite::Import, false) => HelperKind::LiftFlat,
+                (OutlineSite::Export, true) => HelperKind::Lower,
+                (OutlineSite::Export, false) => HelperKind::LowerFlat,
+                (OutlineSite::PostReturn, _) => HelperKind::Dealloc,
+            };
+            outlining.result = outline(self, ty, kind);
+        }
+        outlining
+    }
+
+    fn export_self_arg(&self) -> &'static str {
+        if self.gen.opts.mutable_resources {
+            "&mut self"
+        } else {
+            "&self"
+        }
+    }
+
+    /// Prints the `Rep*` type of the exported resource `id` when its
+    /// implementation is the associated type of `interface_trait` registered
+    /// with `export!`, rather than being named in `Opts::exports`.
+    fn print_resource_rep(&mut self, id: TypeId, interface_trait: &str) {
+        let name = self.resolve.types[id].name.as_deref().unwrap();
+        let camel = name.to_upper_camel_case();
+        let rt = self.gen.runtime_path();
+        uwriteln!(
+            self.src,
+            r#"
+                /// A `{name}` resource, holding the value of
+                /// `{interface_trait}::{camel}` it was created with.
+                pub struct Rep{camel}({rt}::boxed::Box<dyn ::core::any::Any>);
+
+                impl Rep{camel} {{
+                    pub fn new<T: {camel} + 'static>(rep: T) -> Self {{
+                        Self({rt}::boxed::Box::new(rep))
+                    }}
+
+                    /// Returns the value of the resource, panicking if it isn't
+                    /// a `T`.
+                    pub fn get<T: {camel} + 'static>(&self) -> &T {{
+                        self.0
+                            .downcast_ref()
+                            .expect("resource `{name}` has a different type")
+                    }}
+
+                    /// Returns the value of the resource, panicking if it isn't
+                    /// a `T`.
+                    pub fn get_mut<T: {camel} + 'static>(&mut self) -> &mut T {{
+                        self.0
+                            .downcast_mut()
+                            .expect("resource `{name}` has a different type")
+                    }}
+                }}
+
+                impl ::core::fmt::Debug for Rep{camel} {{
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {{
+                        f.debug_struct("Rep{camel}").finish_non_exhaustive()
+                    }}
+                }}
+            "#
+        );
+    }
+
     fn finish_resource_export(&mut self, id: TypeId) {
         self.gen.resources.entry(id).or_default();
         let info = &self.gen.resources[&id];
         let name = self.resolve.types[id].name.as_deref().unwrap();
         let camel = name.to_upper_camel_case();
+        // The type that the resource's representation is boxed as.
+        let stored = if self.gen.opts.mutable_resources {
+            format!("::core::cell::RefCell<Rep{camel}>")
+        } else {
+            format!("Rep{camel}")
+        };
+        let new_stored = if self.gen.opts.mutable_resources {
+            "::core::cell::RefCell::new(rep)"
+        } else {
+            "rep"
+        };
         let snake = to_rust_ident(name);
         let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
         let interface_name = if let TypeOwner::Interface(id) = self.resolve.types[id].owner {
@@ -753,7 +2166,13 @@ impl InterfaceGenerator<'_> {
                         #[allow(unused_imports)]
                         use {rt}::boxed::Box;
 
-                        drop(Box::from_raw(::core::mem::transmute::<isize, *mut Rep{camel}>(
+                        {rt}::handles::release(
+                            "[export]{interface_name}#{name}",
+                            {rt}::handles::Kind::Rep,
+                            arg0,
+                            "dtor",
+                        );
+                        drop(Box::from_raw(::core::mem::transmute::<isize, *mut {stored}>(
                             arg0.try_into().unwrap(),
                         )))
                     }}
@@ -773,12 +2192,25 @@ impl InterfaceGenerator<'_> {
                     impl Own{camel} {{
                         #[doc(hidden)]
                         pub unsafe fn from_handle(handle: i32) -> Self {{
+                            {rt}::handles::acquire(
+                                "[export]{interface_name}#{name}",
+                                {rt}::handles::Kind::Own,
+                                handle,
+                                "from_handle",
+                            );
                             Self {{ handle }}
                         }}
 
                         #[doc(hidden)]
                         pub fn into_handle(self) -> i32 {{
-                            ::core::mem::ManuallyDrop::new(self).handle
+                            let handle = ::core::mem::ManuallyDrop::new(self).handle;
+                            {rt}::handles::release(
+                                "[export]{interface_name}#{name}",
+                                {rt}::handles::Kind::Own,
+                                handle,
+                                "into_handle",
+                            );
+                            handle
                         }}
 
                         pub fn new(rep: Rep{camel}) -> Own{camel} {{
@@ -796,23 +2228,33 @@ impl InterfaceGenerator<'_> {
                                 #[cfg(not(target_arch = "wasm32"))]
                                 unsafe fn wit_import(_n: i32) -> i32 {{ unreachable!() }}
 
-                                Own{camel} {{
-                                    handle: wit_import(
-                                        ::core::mem::transmute::<*mut Rep{camel}, isize>(
-                                            Box::into_raw(Box::new(rep))
-                                        )
-                                            .try_into()
-                                            .unwrap(),
-                                    ),
-                                }}
+                                let rep = ::core::mem::transmute::<*mut {stored}, isize>(
+                                    Box::into_raw(Box::new({new_stored}))
+                                )
+                                    .try_into()
+                                    .unwrap();
+                                {rt}::handles::acquire(
+                                    "[export]{interface_name}#{name}",
+                                    {rt}::handles::Kind::Rep,
+                                    rep,
+                                    "new",
+                                );
+                                let handle = wit_import(rep);
+                                {rt}::handles::acquire(
+                                    "[export]{interface_name}#{name}",
+                                    {rt}::handles::Kind::Own,
+                                    handle,
+                                    "new",
+                                );
+                                Own{camel} {{ handle }}
                             }}
                         }}
                     }}
 
                     impl core::ops::Deref for Own{camel} {{
-                        type Target = Rep{camel};
+                        type Target = {stored};
 
-                        fn deref(&self) -> &Rep{camel} {{
+                        fn deref(&self) -> &{stored} {{
                             unsafe {{
                                 #[cfg(target_arch = "wasm32")]
                                 #[link(wasm_import_module = "[export]{interface_name}")]
@@ -824,9 +2266,14 @@ impl InterfaceGenerator<'_> {
                                 #[cfg(not(target_arch = "wasm32"))]
                                 unsafe fn wit_import(_n: i32) -> i32 {{ unreachable!() }}
 
-                                ::core::mem::transmute::<isize, &Rep{camel}>(
-                                    wit_import(self.handle).try_into().unwrap()
-                                )
+                                let rep = wit_import(self.handle);
+                                {rt}::handles::check(
+                                    "[export]{interface_name}#{name}",
+                                    {rt}::handles::Kind::Rep,
+                                    rep,
+                                    "deref",
+                                );
+                                ::core::mem::transmute::<isize, &{stored}>(rep.try_into().unwrap())
                             }}
                         }}
                     }}
@@ -844,6 +2291,12 @@ impl InterfaceGenerator<'_> {
                                 #[cfg(not(target_arch = "wasm32"))]
                                 fn wit_import(_n: i32) {{ unreachable!() }}
 
+                                {rt}::handles::release(
+                                    "[export]{interface_name}#{name}",
+                                    {rt}::handles::Kind::Own,
+                                    self.handle,
+                                    "drop",
+                                );
                                 wit_import(self.handle)
                             }}
                         }}
@@ -896,14 +2349,11 @@ impl InterfaceGenerator<'_> {
         let module = self.finish();
         let module = format!(
             "
-                #[allow(clippy::all)]
-                pub mod {snake} {{
-                    #[used]
-                    #[doc(hidden)]
-                    #[cfg(target_arch = \"wasm32\")]
-                    static __FORCE_SECTION_REF: fn() = {path_to_root}__link_section;
-                    {module}
-                }}
+                #[used]
+                #[doc(hidden)]
+                #[cfg(target_arch = \"wasm32\")]
+                static __FORCE_SECTION_REF: fn() = {path_to_root}__link_section;
+                {module}
             ",
         );
         let map = if self.in_import {
@@ -911,7 +2361,9 @@ impl InterfaceGenerator<'_> {
         } else {
             &mut self.gen.export_modules
         };
-        map.entry(pkg).or_insert(Vec::new()).push(module);
+        map.entry(pkg)
+            .or_insert(Vec::new())
+            .push((snake.to_string(), module));
     }
 
     fn generate_guest_import(&mut self, func: &Function) {
@@ -941,9 +2393,23 @@ impl InterfaceGenerator<'_> {
             ",
             rt = self.gen.runtime_path()
         ));
+        let interface = match self.identifier {
+            Identifier::Interface(_, key) => Some(key),
+            Identifier::World(_) => None,
+        };
+        self.instrument_call(func, interface, "Import");
+        if func.results.iter_types().any(|ty| self.type_has_list(ty)) {
+            uwriteln!(
+                self.src,
+                "let _results = {}::realloc::import_results();",
+                self.gen.runtime_path()
+            );
+        }
         self.src.push_str("unsafe {\n");
 
+        let outlining = self.call_outlining(func, OutlineSite::Import);
         let mut f = FunctionBindgen::new(self, params, None);
+        f.outlining = outlining;
         f.gen.resolve.call(
             AbiVariant::GuestImport,
             LiftLower::LowerArgsLiftResults,
@@ -952,6 +2418,7 @@ impl InterfaceGenerator<'_> {
         );
         let FunctionBindgen {
             needs_cleanup_list,
+            needs_remap_keep,
             src,
             import_return_pointer_area_size,
             import_return_pointer_area_align,
@@ -961,6 +2428,13 @@ impl InterfaceGenerator<'_> {
         if needs_cleanup_list {
             self.src.push_str("let mut cleanup_list = Vec::new();\n");
         }
+        if needs_remap_keep {
+            uwriteln!(
+                self.src,
+                "let mut remap_keep: Vec<{}::boxed::Box<dyn ::core::any::Any>> = Vec::new();",
+                self.gen.runtime_path()
+            );
+        }
         if import_return_pointer_area_size > 0 {
             uwrite!(
                 self.src,
@@ -975,48 +2449,597 @@ impl InterfaceGenerator<'_> {
 
         self.src.push_str("}\n");
         self.src.push_str("}\n");
+
+        self.generate_dispatch_shim(func, None);
     }
 
-    fn generate_guest_export(
-        &mut self,
-        func: &Function,
-        interface_name: Option<&WorldKey>,
-        trait_name: &str,
-    ) {
-        if self.gen.skip.contains(&func.name) {
-            return;
+    /// Returns the suffix converting the error returned by an implementation of
+    /// the export `func` to its WIT type with `Opts::error_conversion`.
+    fn convert_error(&mut self, func: &Function) -> String {
+        if !self.gen.opts.error_conversion || matches!(func.kind, FunctionKind::Constructor(_)) {
+            return String::new();
+        }
+        let Some((_, err)) = convertible_error(self.resolve, &func.results) else {
+            return String::new();
+        };
+        if self.displays_error(err) {
+            format!(
+                ".map_err(|e| {}::string::ToString::to_string(&e))",
+                self.gen.runtime_path()
+            )
+        } else {
+            let err = self.type_string(err, TypeMode::Owned);
+            format!(".map_err(|e| -> {err} {{ e.into() }})")
         }
+    }
 
-        let name_snake = func.name.to_snake_case().replace('.', "_");
-        let wasm_module_export_name = interface_name.map(|k| self.resolve.name_world_key(k));
-        let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
-        let export_name = func.core_export_name(wasm_module_export_name.as_deref());
+    /// Reports the rest of the function being generated, the glue of a call
+    /// of `func`, to the instrumentation hook.
+    fn instrument_call(&mut self, func: &Function, interface: Option<&WorldKey>, direction: &str) {
+        if !self.gen.opts.instrument {
+            return;
+        }
+        let interface = match interface {
+            Some(key) => format!("Some({:?})", self.resolve.name_world_key(key)),
+            None => "None".to_string(),
+        };
+        uwrite!(
+            self.src,
+            "
+                let _call = {rt}::instrument::enter({rt}::instrument::Call {{
+                    interface: {interface},
+                    function: {:?},
+                    direction: {rt}::instrument::Direction::{direction},
+                }});
+            ",
+            func.name,
+            rt = self.gen.runtime_path(),
+        );
+    }
+
+    /// Returns the name `func` is called by dynamically, as in `reflect`.
+    fn dynamic_name(&self, func: &Function) -> String {
+        match self.identifier {
+            Identifier::Interface(_, key) => {
+                format!("{}#{}", self.resolve.name_world_key(key), func.name)
+            }
+            Identifier::World(_) => func.name.clone(),
+        }
+    }
+
+    /// Generates the shim of `func` in `DISPATCH` with `Opts::dispatch`,
+    /// lowering its arguments from `Val`s and lifting its results to `Val`s.
+    ///
+    /// Exports are called through a copy of the glue called by the component,
+    /// calling the implementation of the trait `trait_name`.
+    fn generate_dispatch_shim(&mut self, func: &Function, trait_name: Option<&str>) {
+        if !self.gen.opts.dispatch
+            || !matches!(func.kind, FunctionKind::Freestanding)
+            || self.gen.skip.contains(&func.name)
+        {
+            return;
+        }
+        let types = func.params.iter().map(|(_, ty)| ty);
+        if !types
+            .chain(func.results.iter_types())
+            .all(|ty| self.dispatchable(ty))
+        {
+            return;
+        }
+        let name = self.dynamic_name(func);
+        let rt = self.gen.runtime_path().to_string();
+        let (direction, call) = if self.in_import {
+            ("import", DynamicCall::Import)
+        } else {
+            ("export", DynamicCall::Export)
+        };
+        let shim = format!("__dispatch_{direction}_{}", func.name.to_snake_case());
         uwrite!(
             self.src,
             "
                 #[doc(hidden)]
-                #[export_name = \"{export_prefix}{export_name}\"]
-                #[allow(non_snake_case)]
-                unsafe extern \"C\" fn __export_{name_snake}(\
+                #[allow(unused_unsafe, clippy::all)]
+                pub fn {shim}(args: &[{rt}::Val]) -> {rt}::vec::Vec<{rt}::Val> {{
+                    #[allow(unused_imports)]
+                    use {rt}::{{alloc, vec::Vec, string::String}};
+                    {rt}::dynamic::check_arity(args, {}, {name:?});
             ",
+            func.params.len(),
+        );
+        if let Some(trait_name) = trait_name {
+            self.generate_export_abi(func, trait_name);
+        }
+        if self.in_import && func.results.iter_types().any(|ty| self.type_has_list(ty)) {
+            uwriteln!(self.src, "let _results = {rt}::realloc::import_results();");
+        }
+        self.src.push_str("unsafe {\n");
+
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestImport, func);
+        let mut f = FunctionBindgen::new(self, Vec::new(), None);
+        f.dynamic = Some(Dynamic {
+            call,
+            func: name.clone(),
+            params_area: call == DynamicCall::Export && sig.indirect_params,
+        });
+        f.gen.resolve.call(
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            func,
+            &mut f,
         );
+        let FunctionBindgen {
+            needs_cleanup_list,
+            src,
+            import_return_pointer_area_size,
+            import_return_pointer_area_align,
+            ..
+        } = f;
+        if needs_cleanup_list {
+            self.src.push_str("let mut cleanup_list = Vec::new();\n");
+        }
+        if import_return_pointer_area_size > 0 {
+            uwrite!(
+                self.src,
+                "
+                    #[repr(align({import_return_pointer_area_align}))]
+                    struct RetArea([u8; {import_return_pointer_area_size}]);
+                    let mut ret_area = ::core::mem::MaybeUninit::<RetArea>::uninit();
+                ",
+            );
+        }
+        self.src.push_str(&String::from(src));
+        self.src.push_str("}\n}\n");
 
+        let path = match self.identifier {
+            Identifier::Interface(id, _) => format!("{}::", self.gen.interface_names[&id]),
+            Identifier::World(_) => String::new(),
+        };
+        let shims = if self.in_import {
+            &mut self.gen.dispatch_imports
+        } else {
+            &mut self.gen.dispatch_exports
+        };
+        shims.push((name, format!("{path}{shim}")));
+    }
+
+    /// Generates `abi`, a copy of the glue of the export `func` which its
+    /// dispatch shim calls, as the glue the component calls can't be named.
+    fn generate_export_abi(&mut self, func: &Function, trait_name: &str) {
         let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
         let mut params = Vec::new();
+        self.src.push_str("unsafe fn abi(");
         for (i, param) in sig.params.iter().enumerate() {
-            let name = format!("arg{}", i);
+            let name = format!("arg{i}");
             uwrite!(self.src, "{name}: {},", wasm_type(*param));
             params.push(name);
         }
         self.src.push_str(")");
+        if let [result] = &sig.results[..] {
+            uwrite!(self.src, " -> {}", wasm_type(*result));
+        }
+        self.src.push_str(" {\n");
+        let glue = self.export_glue(func, params, trait_name, CallOutlining::default());
+        self.src.push_str(&glue);
+        self.src.push_str("}\n");
+    }
 
-        match sig.results.len() {
-            0 => {}
-            1 => {
-                uwrite!(self.src, " -> {}", wasm_type(sig.results[0]));
+    /// Returns the glue of the export `func` lifting its arguments `params`,
+    /// calling the implementation of the trait `trait_name` and lowering its
+    /// results.
+    fn export_glue(
+        &mut self,
+        func: &Function,
+        params: Vec<String>,
+        trait_name: &str,
+        outlining: CallOutlining,
+    ) -> String {
+        let dynamic = self.gen.opts.dynamic_exports.then(|| Dynamic {
+            call: DynamicCall::Handler,
+            func: self.dynamic_name(func),
+            params_area: false,
+        });
+        let mut f = FunctionBindgen::new(self, params, Some(trait_name));
+        f.outlining = outlining;
+        f.dynamic = dynamic;
+        f.gen.resolve.call(
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+        );
+        let FunctionBindgen {
+            needs_cleanup_list,
+            src,
+            ..
+        } = f;
+        assert!(!needs_cleanup_list);
+        src.into()
+    }
+
+    /// Returns whether values of `ty` can be represented by `Val`s.
+    fn dispatchable(&self, ty: &Type) -> bool {
+        let id = match ty {
+            Type::Id(id) => *id,
+            _ => return true,
+        };
+        match &self.resolve.types[id].kind {
+            TypeDefKind::Record(r) => r.fields.iter().all(|f| self.dispatchable(&f.ty)),
+            TypeDefKind::Tuple(t) => t.types.iter().all(|ty| self.dispatchable(ty)),
+            TypeDefKind::Variant(v) => v
+                .cases
+                .iter()
+                .all(|c| c.ty.iter().all(|ty| self.dispatchable(ty))),
+            TypeDefKind::Union(u) => u.cases.iter().all(|c| self.dispatchable(&c.ty)),
+            TypeDefKind::Result(r) => [&r.ok, &r.err]
+                .into_iter()
+                .flatten()
+                .all(|ty| self.dispatchable(ty)),
+            TypeDefKind::List(ty) | TypeDefKind::Option(ty) | TypeDefKind::Type(ty) => {
+                self.dispatchable(ty)
             }
-            _ => unimplemented!(),
+            TypeDefKind::Enum(_) | TypeDefKind::Flags(_) => true,
+            TypeDefKind::Resource
+            | TypeDefKind::Handle(_)
+            | TypeDefKind::Future(_)
+            | TypeDefKind::Stream(_)
+            | TypeDefKind::Unknown => false,
         }
+    }
+
+    fn type_string(&mut self, ty: &Type, mode: TypeMode) -> String {
+        let prev = mem::take(&mut self.src);
+        self.print_ty(ty, mode);
+        String::from(mem::replace(&mut self.src, prev))
+    }
+
+    /// Returns the type which would have been generated for `ty`, a type
+    /// remapped with `Opts::remap_types`, which its conversions go through.
+    fn wit_type_string(&mut self, ty: &Type) -> String {
+        let prev = mem::take(&mut self.src);
+        let id = match ty {
+            Type::String if self.use_raw_strings() => {
+                self.push_vec_name();
+                self.push_str(&format!("::<{}>", self.raw_string_unit()));
+                None
+            }
+            Type::String => {
+                self.push_string_name();
+                None
+            }
+            Type::Id(id) => Some(*id),
+            _ => unreachable!(),
+        };
+        if let Some(id) = id {
+            let ty = &self.resolve.types[id];
+            // Named anonymous-like types are remapped as a whole, so their
+            // definition can't be used.
+            match &ty.kind {
+                TypeDefKind::List(element) => self.print_list(element, TypeMode::Owned),
+                TypeDefKind::Option(payload) if ty.name.is_some() => {
+                    self.push_str("Option<");
+                    self.print_ty(payload, TypeMode::Owned);
+                    self.push_str(">");
+                }
+                TypeDefKind::Result(r) if ty.name.is_some() => {
+                    self.push_str("Result<");
+                    self.print_optional_ty(r.ok.as_ref(), TypeMode::Owned);
+                    self.push_str(",");
+                    self.print_optional_ty(r.err.as_ref(), TypeMode::Owned);
+                    self.push_str(">");
+                }
+                TypeDefKind::Tuple(t) if ty.name.is_some() => {
+                    self.push_str("(");
+                    for ty in t.types.iter() {
+                        self.print_ty(ty, TypeMode::Owned);
+                        self.push_str(",");
+                    }
+                    self.push_str(")");
+                }
+                _ => self.print_tyid(id, TypeMode::Owned),
+            }
+        }
+        String::from(mem::replace(&mut self.src, prev))
+    }
+
+    /// Defines a named tuple, option, result or list remapped with
+    /// `Opts::remap_types` as an alias of its remapped type, returning whether
+    /// it's remapped.
+    fn print_remapped_typedef(&mut self, id: TypeId, docs: &Docs) -> bool {
+        let Some(remapped) = self.remapped_type(&Type::Id(id)) else {
+            return false;
+        };
+        let name = self.resolve.types[id].name.as_deref().unwrap();
+        self.rustdoc(docs);
+        uwriteln!(
+            self.src,
+            "pub type {} = {remapped};",
+            name.to_upper_camel_case()
+        );
+        true
+    }
+
+    /// Returns the path from the module of this interface to the root of the
+    /// bindings.
+    fn path_to_root(&self) -> String {
+        let mut path = String::new();
+        if let Identifier::Interface(_, name) = self.identifier {
+            if !self.in_import {
+                path.push_str("super::");
+            }
+            match name {
+                WorldKey::Name(_) => {
+                    path.push_str("super::");
+                }
+                WorldKey::Interface(_) => {
+                    path.push_str("super::super::super::");
+                }
+            }
+        }
+        path
+    }
+
+    /// Implements the `serde` traits for flags, which are serialized as the
+    /// list of the names of the flags which are set.
+    fn print_serde_flags(&mut self, name: &str, flags: &Flags) {
+        let Some(serde) = self.serde_path() else {
+            return;
+        };
+        let name = name.to_upper_camel_case();
+        let rt = self.gen.runtime_path();
+        let mut serialize = String::new();
+        let mut deserialize = String::new();
+        for flag in flags.flags.iter() {
+            let flag_name = flag.name.to_shouty_snake_case();
+            uwrite!(
+                serialize,
+                "if self.contains(Self::{flag_name}) {{ seq.serialize_element({:?})?; }}",
+                flag.name
+            );
+            uwrite!(deserialize, "{:?} => Self::{flag_name},", flag.name);
+        }
+        let names = flags
+            .flags
+            .iter()
+            .map(|flag| format!("{:?},", flag.name))
+            .collect::<String>();
+        uwrite!(
+            self.src,
+            "
+                impl {serde}::Serialize for {name} {{
+                    fn serialize<S: {serde}::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {{
+                        use {serde}::ser::SerializeSeq;
+                        let mut seq = serializer.serialize_seq(None)?;
+                        {serialize}
+                        seq.end()
+                    }}
+                }}
+
+                impl<'de> {serde}::Deserialize<'de> for {name} {{
+                    fn deserialize<D: {serde}::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {{
+                        let names = <{rt}::vec::Vec<{rt}::string::String> as {serde}::Deserialize>::deserialize(deserializer)?;
+                        let mut flags = Self::empty();
+                        for name in names {{
+                            flags |= match name.as_str() {{
+                                {deserialize}
+                                _ => return Err(<D::Error as {serde}::de::Error>::unknown_variant(&name, &[{names}])),
+                            }};
+                        }}
+                        Ok(flags)
+                    }}
+                }}
+            "
+        );
+    }
+
+    /// Generates the `case_conversions` of the enum `name`.
+    fn print_enum_conversions(&mut self, id: TypeId, name: &str, enum_: &Enum) {
+        if !self.gen.opts.case_conversions {
+            return;
+        }
+        let ty = name.to_upper_camel_case();
+        let rt = self.gen.runtime_path();
+        let mut from_discriminant = String::new();
+        let mut from_name = String::new();
+        let mut to_name = String::new();
+        for (i, case) in enum_.cases.iter().enumerate() {
+            let case_name = case.name.to_upper_camel_case();
+            uwrite!(from_discriminant, "{i} => Ok({ty}::{case_name}),");
+            uwrite!(from_name, "{:?} => Ok({ty}::{case_name}),", case.name);
+            uwrite!(to_name, "{ty}::{case_name} => {:?},", case.name);
+        }
+        uwrite!(
+            self.src,
+            "
+                impl ::core::convert::TryFrom<u32> for {ty} {{
+                    type Error = {rt}::names::UnknownCase;
+
+                    fn try_from(discriminant: u32) -> Result<Self, {rt}::names::UnknownCase> {{
+                        match discriminant {{
+                            {from_discriminant}
+                            _ => Err({rt}::names::UnknownCase::new({name:?}, discriminant)),
+                        }}
+                    }}
+                }}
+
+                impl ::core::convert::TryFrom<u16> for {ty} {{
+                    type Error = {rt}::names::UnknownCase;
+
+                    fn try_from(discriminant: u16) -> Result<Self, {rt}::names::UnknownCase> {{
+                        Self::try_from(u32::from(discriminant))
+                    }}
+                }}
+
+                impl ::core::convert::TryFrom<u8> for {ty} {{
+                    type Error = {rt}::names::UnknownCase;
+
+                    fn try_from(discriminant: u8) -> Result<Self, {rt}::names::UnknownCase> {{
+                        Self::try_from(u32::from(discriminant))
+                    }}
+                }}
+
+                impl ::core::str::FromStr for {ty} {{
+                    type Err = {rt}::names::UnknownCase;
+
+                    fn from_str(name: &str) -> Result<Self, {rt}::names::UnknownCase> {{
+                        match name {{
+                            {from_name}
+                            _ => Err({rt}::names::UnknownCase::new({name:?}, name)),
+                        }}
+                    }}
+                }}
+            "
+        );
+        // Error enums already display their name along with their code.
+        if !self.info(id).error {
+            uwrite!(
+                self.src,
+                "
+                    impl ::core::fmt::Display for {ty} {{
+                        fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {{
+                            f.write_str(match self {{
+                                {to_name}
+                            }})
+                        }}
+                    }}
+                "
+            );
+        }
+    }
+
+    /// Generates the `case_conversions` of the flags `name`.
+    fn print_flags_conversions(&mut self, name: &str, flags: &Flags) {
+        if !self.gen.opts.case_conversions {
+            return;
+        }
+        let ty = name.to_upper_camel_case();
+        let rt = self.gen.runtime_path();
+        let mut names = String::new();
+        let mut from_name = String::new();
+        for flag in flags.flags.iter() {
+            let flag_name = flag.name.to_shouty_snake_case();
+            uwrite!(names, "(Self::{flag_name}, {:?}),", flag.name);
+            uwrite!(from_name, "{:?} => Some(Self::{flag_name}),", flag.name);
+        }
+        uwrite!(
+            self.src,
+            "
+                impl {ty} {{
+                    /// Returns the WIT names of the flags set in `self`.
+                    pub fn wit_names(&self) -> impl Iterator<Item = &'static str> {{
+                        let flags = *self;
+                        ::core::iter::IntoIterator::into_iter([{names}])
+                            .filter(move |(flag, _)| flags.contains(*flag))
+                            .map(|(_, name)| name)
+                    }}
+
+                    /// Returns the flag named `name` in WIT, if any.
+                    pub fn from_wit_name(name: &str) -> Option<Self> {{
+                        match name {{
+                            {from_name}
+                            _ => None,
+                        }}
+                    }}
+                }}
+
+                impl ::core::str::FromStr for {ty} {{
+                    type Err = {rt}::names::UnknownCase;
+
+                    fn from_str(names: &str) -> Result<Self, Self::Err> {{
+                        let mut flags = Self::empty();
+                        for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {{
+                            flags |= Self::from_wit_name(name)
+                                .ok_or_else(|| {rt}::names::UnknownCase::new({name:?}, name))?;
+                        }}
+                        Ok(flags)
+                    }}
+                }}
+
+                impl ::core::fmt::Display for {ty} {{
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {{
+                        for (i, name) in self.wit_names().enumerate() {{
+                            if i > 0 {{
+                                f.write_str(\", \")?;
+                            }}
+                            f.write_str(name)?;
+                        }}
+                        Ok(())
+                    }}
+                }}
+            "
+        );
+    }
+
+    /// Generates the export of `func`, a function of the trait `trait_name`.
+    ///
+    /// With `Opts::export_macro` the implementations of resources are the
+    /// associated types of `interface_trait`, the trait of the interface's
+    /// freestanding functions.
+    fn generate_guest_export(
+        &mut self,
+        func: &Function,
+        interface_name: Option<&WorldKey>,
+        trait_name: &str,
+        interface_trait: &str,
+    ) {
+        if self.gen.skip.contains(&func.name) {
+            return;
+        }
+
+        let name_snake = func.name.to_snake_case().replace('.', "_");
+        let wasm_module_export_name = interface_name.map(|k| self.resolve.name_world_key(k));
+        let export_prefix = self.gen.opts.export_prefix.clone().unwrap_or_default();
+        let export_name = func.core_export_name(wasm_module_export_name.as_deref());
+        let export_macro = self.gen.opts.export_macro;
+        let path = match self.identifier {
+            Identifier::Interface(id, _) => format!("{}::", self.gen.interface_names[&id]),
+            Identifier::World(_) => String::new(),
+        };
+        // The type implementing `trait_name`, as passed to `export!`.
+        let (bound, impl_ty) = match &func.kind {
+            FunctionKind::Freestanding => (trait_name.to_owned(), "$ty".to_owned()),
+            FunctionKind::Method(_) | FunctionKind::Static(_) | FunctionKind::Constructor(_) => (
+                format!("{trait_name} + 'static"),
+                format!("<$ty as $($path_to_types)*::{path}{interface_trait}>::{trait_name}"),
+            ),
+        };
+        if export_macro {
+            uwrite!(
+                self.src,
+                "
+                    #[doc(hidden)]
+                    #[allow(non_snake_case)]
+                    pub unsafe fn _export_{name_snake}_cabi<T: {bound}>(\
+                ",
+            );
+        } else {
+            uwrite!(
+                self.src,
+                "
+                    #[doc(hidden)]
+                    #[export_name = \"{export_prefix}{export_name}\"]
+                    #[allow(non_snake_case)]
+                    unsafe extern \"C\" fn __export_{name_snake}(\
+                ",
+            );
+        }
+
+        let sig = self.resolve.wasm_signature(AbiVariant::GuestExport, func);
+        let mut params = Vec::new();
+        let mut params_decl = String::new();
+        for (i, param) in sig.params.iter().enumerate() {
+            let name = format!("arg{}", i);
+            uwrite!(params_decl, "{name}: {},", wasm_type(*param));
+            params.push(name);
+        }
+        self.src.push_str(&params_decl);
+        self.src.push_str(")");
+
+        let results_decl = match sig.results.len() {
+            0 => String::new(),
+            1 => format!(" -> {}", wasm_type(sig.results[0])),
+            _ => unimplemented!(),
+        };
+        self.src.push_str(&results_decl);
 
         self.push_str(" {");
 
@@ -1043,44 +3066,78 @@ impl InterfaceGenerator<'_> {
             ",
             rt = self.gen.runtime_path()
         );
+        if self.gen.opts.singleton && matches!(func.kind, FunctionKind::Freestanding) {
+            self.src.push_str("let mut instance = __instance();\n");
+        }
+        self.instrument_call(func, interface_name, "Export");
 
-        let mut f = FunctionBindgen::new(self, params, Some(trait_name));
-        f.gen.resolve.call(
-            AbiVariant::GuestExport,
-            LiftLower::LiftArgsLowerResults,
-            func,
-            &mut f,
-        );
-        let FunctionBindgen {
-            needs_cleanup_list,
-            src,
-            ..
-        } = f;
-        assert!(!needs_cleanup_list);
-        self.src.push_str(&String::from(src));
+        let outlining = self.call_outlining(func, OutlineSite::Export);
+        let glue = self.export_glue(func, params.clone(), trait_name, outlining);
+        self.src.push_str(&glue);
         self.src.push_str("}\n");
 
-        if self.resolve.guest_export_needs_post_return(func) {
-            let export_prefix = self.gen.opts.export_prefix.as_deref().unwrap_or("");
-            uwrite!(
-                self.src,
+        if export_macro {
+            self.gen.export_macro_shims.push(format!(
                 "
                     const _: () = {{
-                    #[doc(hidden)]
-                    #[export_name = \"{export_prefix}cabi_post_{export_name}\"]
-                    #[allow(non_snake_case)]
-                    unsafe extern \"C\" fn __post_return_{name_snake}(\
-                "
-            );
+                        #[export_name = \"{export_prefix}{export_name}\"]
+                        unsafe extern \"C\" fn export({params_decl}){results_decl} {{
+                            $($path_to_types)*::{path}_export_{name_snake}_cabi::<{impl_ty}>({args})
+                        }}
+                    }};
+                ",
+                args = params.join(", "),
+            ));
+        }
+
+        if self.resolve.guest_export_needs_post_return(func) {
+            if export_macro {
+                uwrite!(
+                    self.src,
+                    "
+                        #[doc(hidden)]
+                        #[allow(non_snake_case)]
+                        pub unsafe fn _post_return_{name_snake}<T: {bound}>(\
+                    "
+                );
+            } else {
+                uwrite!(
+                    self.src,
+                    "
+                        const _: () = {{
+                        #[doc(hidden)]
+                        #[export_name = \"{export_prefix}cabi_post_{export_name}\"]
+                        #[allow(non_snake_case)]
+                        unsafe extern \"C\" fn __post_return_{name_snake}(\
+                    "
+                );
+            }
             let mut params = Vec::new();
+            let mut params_decl = String::new();
             for (i, result) in sig.results.iter().enumerate() {
                 let name = format!("arg{}", i);
-                uwrite!(self.src, "{name}: {},", wasm_type(*result));
+                uwrite!(params_decl, "{name}: {},", wasm_type(*result));
                 params.push(name);
             }
+            self.src.push_str(&params_decl);
             self.src.push_str(") {\n");
+            if export_macro {
+                self.gen.export_macro_shims.push(format!(
+                    "
+                        const _: () = {{
+                            #[export_name = \"{export_prefix}cabi_post_{export_name}\"]
+                            unsafe extern \"C\" fn post_return({params_decl}) {{
+                                $($path_to_types)*::{path}_post_return_{name_snake}::<{impl_ty}>({args})
+                            }}
+                        }};
+                    ",
+                    args = params.join(", "),
+                ));
+            }
 
+            let outlining = self.call_outlining(func, OutlineSite::PostReturn);
             let mut f = FunctionBindgen::new(self, params, Some(trait_name));
+            f.outlining = outlining;
             f.gen.resolve.post_return(func, &mut f);
             let FunctionBindgen {
                 needs_cleanup_list,
@@ -1090,7 +3147,9 @@ impl InterfaceGenerator<'_> {
             assert!(!needs_cleanup_list);
             self.src.push_str(&String::from(src));
             self.src.push_str("}\n");
-            self.src.push_str("};\n");
+            if !export_macro {
+                self.src.push_str("};\n");
+            }
         }
     }
 
@@ -1100,6 +3159,7 @@ impl InterfaceGenerator<'_> {
         pkg: Option<&PackageName>,
         name: &str,
         in_interface: bool,
+        resources: &[TypeId],
         funcs: &[&Function],
     ) {
         let path = if let Some(pkg) = pkg {
@@ -1130,17 +3190,33 @@ impl InterfaceGenerator<'_> {
         };
 
         uwriteln!(self.src, "impl {qualified_name} for Stub {{");
+        for ty in resources {
+            let camel = self.resolve.types[*ty]
+                .name
+                .as_deref()
+                .unwrap()
+                .to_upper_camel_case();
+            uwriteln!(self.src, "type {camel} = Stub;");
+        }
 
         for &func in funcs {
             if self.gen.skip.contains(&func.name) {
                 continue;
             }
+            if self.gen.opts.error_conversion {
+                self.print_error_type(func, true);
+            }
             let mut sig = FnSig::default();
             sig.use_item_name = true;
             sig.private = true;
+            sig.borrow_canonical_params = self.gen.opts.borrowed_export_params;
+            sig.convert_errors = self.gen.opts.error_conversion;
+            sig.async_ = self.gen.opts.async_exports;
             if let FunctionKind::Method(_) = &func.kind {
-                sig.self_arg = Some("&self".into());
+                sig.self_arg = Some(self.export_self_arg().into());
                 sig.self_is_first_param = true;
+            } else if self.gen.opts.singleton && resource.is_none() {
+                sig.self_arg = Some("&mut self".into());
             }
             self.print_signature(func, TypeMode::Owned, &sig);
             self.src.push_str("{ unreachable!() }\n");
@@ -1160,36 +3236,56 @@ impl<'a> RustGenerator<'a> for InterfaceGenerator<'a> {
     }
 
     fn path_to_interface(&self, interface: InterfaceId) -> Option<String> {
-        let mut path = String::new();
-        if let Identifier::Interface(cur, name) = self.identifier {
+        if let Identifier::Interface(cur, _) = self.identifier {
             if cur == interface {
                 return None;
             }
-            if !self.in_import {
-                path.push_str("super::");
-            }
-            match name {
-                WorldKey::Name(_) => {
-                    path.push_str("super::");
-                }
-                WorldKey::Interface(_) => {
-                    path.push_str("super::super::super::");
-                }
-            }
         }
+        let mut path = self.path_to_root();
         let name = &self.gen.interface_names[&interface];
         path.push_str(&name);
         Some(path)
     }
 
+    fn remapped_type(&self, ty: &Type) -> Option<String> {
+        self.gen.remapped.get(ty).cloned()
+    }
+
     fn std_feature(&self) -> bool {
         self.gen.opts.std_feature
     }
 
+    fn record_builders(&self) -> bool {
+        self.gen.opts.record_builders
+    }
+
     fn use_raw_strings(&self) -> bool {
         self.gen.opts.raw_strings
     }
 
+    fn use_mutable_resources(&self) -> bool {
+        self.gen.opts.mutable_resources
+    }
+
+    fn serde_path(&self) -> Option<&'static str> {
+        self.gen.opts.serde.then_some("wit_bindgen::serde")
+    }
+
+    fn raw_string_unit(&self) -> &'static str {
+        match self.gen.opts.string_encoding {
+            StringEncoding::UTF8 => "u8",
+            StringEncoding::UTF16 | StringEncoding::CompactUTF16 => "u16",
+        }
+    }
+
+    fn can_borrow_strings(&self) -> bool {
+        match self.gen.opts.string_encoding {
+            StringEncoding::UTF8 => true,
+            StringEncoding::UTF16 => self.gen.opts.raw_strings,
+            StringEncoding::CompactUTF16 => false,
+        }
+    }
+
     fn push_vec_name(&mut self) {
         self.push_str(&format!("{rt}::vec::Vec", rt = self.gen.runtime_path()));
     }
@@ -1257,7 +3353,7 @@ impl<'a> RustGenerator<'a> for InterfaceGenerator<'a> {
             self.push_str(" ");
         }
         if self.gen.opts.raw_strings {
-            self.push_str("[u8]");
+            self.push_str(&format!("[{}]", self.raw_string_unit()));
         } else {
             self.push_str("str");
         }
@@ -1286,7 +3382,9 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
     }
 
     fn type_tuple(&mut self, id: TypeId, _name: &str, tuple: &Tuple, docs: &Docs) {
-        self.print_typedef_tuple(id, tuple, docs);
+        if !self.print_remapped_typedef(id, docs) {
+            self.print_typedef_tuple(id, tuple, docs);
+        }
     }
 
     fn type_flags(&mut self, _id: TypeId, name: &str, flags: &Flags, docs: &Docs) {
@@ -1307,6 +3405,8 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
         }
         self.src.push_str("}\n");
         self.src.push_str("}\n");
+        self.print_serde_flags(name, flags);
+        self.print_flags_conversions(name, flags);
     }
 
     fn type_variant(&mut self, id: TypeId, _name: &str, variant: &Variant, docs: &Docs) {
@@ -1318,68 +3418,888 @@ impl<'a> wit_bindgen_core::InterfaceGenerator<'a> for InterfaceGenerator<'a> {
     }
 
     fn type_option(&mut self, id: TypeId, _name: &str, payload: &Type, docs: &Docs) {
-        self.print_typedef_option(id, payload, docs);
+        if !self.print_remapped_typedef(id, docs) {
+            self.print_typedef_option(id, payload, docs);
+        }
     }
 
     fn type_result(&mut self, id: TypeId, _name: &str, result: &Result_, docs: &Docs) {
-        self.print_typedef_result(id, result, docs);
+        if !self.print_remapped_typedef(id, docs) {
+            self.print_typedef_result(id, result, docs);
+        }
+    }
+
+    fn type_enum(&mut self, id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
+        self.print_typedef_enum(id, name, enum_, docs, &[], Box::new(|_| String::new()));
+        self.print_enum_conversions(id, name, enum_);
+    }
+
+    fn type_alias(&mut self, id: TypeId, _name: &str, ty: &Type, docs: &Docs) {
+        self.print_typedef_alias(id, ty, docs);
+    }
+
+    fn type_list(&mut self, id: TypeId, _name: &str, ty: &Type, docs: &Docs) {
+        if !self.print_remapped_typedef(id, docs) {
+            self.print_type_list(id, ty, docs);
+        }
+    }
+
+    fn type_builtin(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
+        self.rustdoc(docs);
+        self.src
+            .push_str(&format!("pub type {}", name.to_upper_camel_case()));
+        self.src.push_str(" = ");
+        self.print_ty(ty, TypeMode::Owned);
+        self.src.push_str(";\n");
+    }
+}
+
+struct FunctionBindgen<'a, 'b> {
+    gen: &'b mut InterfaceGenerator<'a>,
+    params: Vec<String>,
+    trait_name: Option<&'b str>,
+    src: Source,
+    blocks: Vec<String>,
+    /// Whether each of `blocks` adds to `cleanup_list`.
+    block_cleanups: Vec<bool>,
+    block_storage: Vec<SavedSource>,
+    /// Whether the block being generated adds to `cleanup_list`.
+    block_uses_cleanup_list: bool,
+    tmp: usize,
+    needs_cleanup_list: bool,
+    /// The kind of helper whose body is being generated, if any, in which
+    /// case calls and returns are replaced with the helper's own results.
+    helper: Option<HelperKind>,
+    outlining: CallOutlining,
+    /// Whether remapped import arguments converted within a block are kept
+    /// alive in `remap_keep` until the call returns.
+    needs_remap_keep: bool,
+    cleanup: Vec<(String, String)>,
+    import_return_pointer_area_size: usize,
+    import_return_pointer_area_align: usize,
+    /// Owned lifted parameter expressions which may instead be passed to an
+    /// export as a borrow, mapped to the borrowed expression and the
+    /// statement freeing the argument buffer.
+    borrowable: HashMap<String, (String, String)>,
+    /// Set if `Val`s are lifted and lowered instead of the generated types.
+    dynamic: Option<Dynamic>,
+}
+
+impl<'a, 'b> FunctionBindgen<'a, 'b> {
+    fn new(
+        gen: &'b mut InterfaceGenerator<'a>,
+        params: Vec<String>,
+        trait_name: Option<&'b str>,
+    ) -> FunctionBindgen<'a, 'b> {
+        FunctionBindgen {
+            gen,
+            params,
+            trait_name,
+            src: Default::default(),
+            blocks: Vec::new(),
+            block_cleanups: Vec::new(),
+            block_storage: Vec::new(),
+            block_uses_cleanup_list: false,
+            tmp: 0,
+            needs_cleanup_list: false,
+            helper: None,
+            outlining: CallOutlining::default(),
+            needs_remap_keep: false,
+            cleanup: Vec::new(),
+            import_return_pointer_area_size: 0,
+            import_return_pointer_area_align: 0,
+            borrowable: HashMap::new(),
+            dynamic: None,
+        }
+    }
+
+    /// Returns the body of the list element block just popped, or a call of
+    /// the helper of `kind` outlining it.
+    fn list_element_body(&mut self, element: &Type, kind: HelperKind, body: String) -> String {
+        // Helpers aren't emitted for code which is discarded, nor do they
+        // handle `Val`s.
+        if self.outlining.discarded.is_some() || self.dynamic.is_some() {
+            return body;
+        }
+        let cleanup_list = self.block_cleanups[self.blocks.len()];
+        match self
+            .gen
+            .list_element_helper(element, kind, &body, cleanup_list)
+        {
+            Some((call, cleanup_list)) => {
+                self.needs_cleanup_list |= cleanup_list;
+                self.block_uses_cleanup_list |= cleanup_list;
+                call
+            }
+            None => body,
+        }
+    }
+
+    /// Starts discarding the code generated for a value which is lifted or
+    /// lowered by a helper instead.
+    fn start_discarding(&mut self) {
+        let src = mem::take(&mut self.src);
+        let cleanup = mem::take(&mut self.cleanup);
+        self.outlining.discarded = Some((src, cleanup, self.needs_cleanup_list));
+    }
+
+    fn stop_discarding(&mut self) {
+        if let Some((src, cleanup, needs_cleanup_list)) = self.outlining.discarded.take() {
+            self.src = src;
+            self.cleanup = cleanup;
+            self.needs_cleanup_list = needs_cleanup_list;
+        }
+        // Export parameters are lifted once all of their arguments are known.
+        if let Some((nth, args)) = self.outlining.pending.take() {
+            let call = self.outlining.params[nth]
+                .0
+                .as_ref()
+                .unwrap()
+                .helper
+                .call(&args.join(", "));
+            let tmp = self.tmp();
+            uwriteln!(self.src, "let lifted{tmp} = {call};");
+            self.outlining.values.insert(nth, format!("lifted{tmp}"));
+        }
+    }
+
+    /// Replaces the operands of outlined parameters of the call `inst`,
+    /// stopping discarding the code generated for them.
+    fn outline_params(&mut self, inst: &Instruction<'_>, operands: &mut [String]) {
+        let site = match self.outlining.site {
+            Some(site) => site,
+            None => return,
+        };
+        match inst {
+            Instruction::GetArg { nth } => match site {
+                OutlineSite::Import => {
+                    self.stop_discarding();
+                    let Some((Some(outlined), _)) = self.outlining.params.get(*nth) else {
+                        return;
+                    };
+                    // Owned types are borrowed by the import's signature if
+                    // they contain lists.
+                    let arg = if self.gen.info(outlined.id).has_list {
+                        self.params[*nth].clone()
+                    } else {
+                        format!("&{}", self.params[*nth])
+                    };
+                    let call = outlined.helper.call(&arg);
+                    self.needs_cleanup_list |= outlined.helper.cleanup_list;
+                    let tmp = self.tmp();
+                    uwriteln!(self.src, "let lowered{tmp} = {call};");
+                    self.outlining.values.insert(*nth, format!("lowered{tmp}"));
+                    self.start_discarding();
+                }
+                OutlineSite::Export => {
+                    // Only the first argument of a parameter starts lifting it.
+                    let mut first = 0;
+                    let mut param = None;
+                    for (i, (outlined, flat)) in self.outlining.params.iter().enumerate() {
+                        if first == *nth {
+                            param = Some((i, outlined.is_some(), *flat));
+                            break;
+                        }
+                        first += flat;
+                    }
+                    let Some((i, outlined, flat)) = param else {
+                        return;
+                    };
+                    self.stop_discarding();
+                    if outlined {
+                        let args = self.params[first..first + flat].to_vec();
+                        self.outlining.pending = Some((i, args));
+                        self.start_discarding();
+                    }
+                }
+                OutlineSite::PostReturn => {
+                    if self.outlining.result.is_some() {
+                        self.outlining.result_operand = Some(self.params[*nth].clone());
+                        self.start_discarding();
+                    }
+                }
+            },
+            Instruction::CallWasm { .. } => {
+                self.stop_discarding();
+                let mut first = 0;
+                for (i, (outlined, flat)) in self.outlining.params.iter().enumerate() {
+                    if outlined.is_some() {
+                        let value = &self.outlining.values[&i];
+                        for j in 0..*flat {
+                            operands[first + j] = match flat {
+                                1 => value.clone(),
+                                _ => format!("{value}.{j}"),
+                            };
+                        }
+                    }
+                    first += flat;
+                }
+            }
+            Instruction::CallInterface { .. } => {
+                self.stop_discarding();
+                for (i, value) in self.outlining.values.iter() {
+                    operands[*i] = value.clone();
+                }
+            }
+            Instruction::Return { .. } => {
+                self.stop_discarding();
+                let operand = self.outlining.result_operand.take();
+                let (Some(outlined), Some(operand)) = (&self.outlining.result, operand) else {
+                    return;
+                };
+                match outlined.kind {
+                    HelperKind::Lift | HelperKind::LiftFlat => {
+                        operands[0] = outlined.helper.call(&operand);
+                    }
+                    HelperKind::LowerFlat => {
+                        operands[0] = outlined.helper.call(&format!("&{operand}"));
+                    }
+                    HelperKind::Lower => {
+                        let call = outlined.helper.call(&format!("{operand}, {}", operands[0]));
+                        uwriteln!(self.src, "{call};");
+                    }
+                    HelperKind::Dealloc => {
+                        let call = outlined.helper.call(&operand);
+                        uwriteln!(self.src, "{call};");
+                    }
+                    HelperKind::LowerBorrowed => unreachable!(),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Starts discarding the code generated for the result of the call `inst`
+    /// if it's outlined.
+    fn outline_result(&mut self, inst: &Instruction<'_>, operands: &[String], results: &[String]) {
+        let Some(outlined) = &self.outlining.result else {
+            return;
+        };
+        let operand = match inst {
+            Instruction::CallWasm { .. } if outlined.kind == HelperKind::Lift => {
+                operands.last().unwrap().clone()
+            }
+            Instruction::CallWasm { .. } | Instruction::CallInterface { .. } => results[0].clone(),
+            _ => return,
+        };
+        self.outlining.result_operand = Some(operand);
+        self.start_discarding();
+    }
+
+    /// Returns the type of the value lowered or lifted by `inst`, and whether
+    /// it's lowered, if it's remapped with `Opts::remap_types`.
+    fn remapped_operand(&self, inst: &Instruction<'_>) -> Option<(Type, bool)> {
+        if self.dynamic.is_some() {
+            return None;
+        }
+        let (ty, lower) = match inst {
+            Instruction::StringLower { .. } => (Type::String, true),
+            Instruction::StringLift => (Type::String, false),
+            Instruction::ListCanonLower { element, .. }
+            | Instruction::ListLower { element, .. } => {
+                (Type::Id(*self.gen.gen.remapped_lists.get(*element)?), true)
+            }
+            Instruction::RecordLower { ty, .. }
+            | Instruction::TupleLower { ty, .. }
+            | Instruction::FlagsLower { ty, .. }
+            | Instruction::VariantLower { ty, .. }
+            | Instruction::UnionLower { ty, .. }
+            | Instruction::EnumLower { ty, .. }
+            | Instruction::OptionLower { ty, .. }
+            | Instruction::ResultLower { ty, .. } => (Type::Id(*ty), true),
+            Instruction::ListCanonLift { ty, .. }
+            | Instruction::ListLift { ty, .. }
+            | Instruction::RecordLift { ty, .. }
+            | Instruction::TupleLift { ty, .. }
+            | Instruction::FlagsLift { ty, .. }
+            | Instruction::VariantLift { ty, .. }
+            | Instruction::UnionLift { ty, .. }
+            | Instruction::EnumLift { ty, .. }
+            | Instruction::OptionLift { ty, .. }
+            | Instruction::ResultLift { ty, .. } => (Type::Id(*ty), false),
+            _ => return None,
+        };
+        self.gen.remapped_type(&ty)?;
+        Some((ty, lower))
+    }
+
+    /// Converts the remapped value lowered by the next instruction to the
+    /// type generated for `ty`.
+    fn lower_remapped(&mut self, ty: &Type, operands: &mut [String]) {
+        let remapped = self.gen.remapped_type(ty).unwrap();
+        let wit = self.gen.wit_type_string(ty);
+        let root = self.gen.path_to_root();
+        let convert = format!(
+            "<{remapped} as {root}WitRemap<{wit}>>::to_wit(&{})",
+            operands[0]
+        );
+        let tmp = self.tmp();
+        let has_list = match ty {
+            Type::Id(id) => self.gen.info(*id).has_list,
+            _ => true,
+        };
+        // Imports borrow their arguments' memory for the duration of the call,
+        // which values converted within a block wouldn't outlive.
+        if self.gen.in_import && !self.block_storage.is_empty() && has_list {
+            let rt = self.gen.gen.runtime_path().to_string();
+            self.needs_remap_keep = true;
+            uwriteln!(
+                self.src,
+                "remap_keep.push({rt}::boxed::Box::new({convert}));
+                let remapped{tmp} = remap_keep.last().unwrap().downcast_ref::<{wit}>().unwrap() as *const {wit};"
+            );
+            operands[0] = format!("&*remapped{tmp}");
+        } else {
+            uwriteln!(self.src, "let remapped{tmp} = {convert};");
+            operands[0] = format!("remapped{tmp}");
+        }
+    }
+
+    /// Converts the value lifted by the previous instruction from the type
+    /// generated for `ty` to its remapped type.
+    fn lift_remapped(&mut self, ty: &Type, results: &mut Vec<String>) {
+        let remapped = self.gen.remapped_type(ty).unwrap();
+        let wit = self.gen.wit_type_string(ty);
+        let root = self.gen.path_to_root();
+        let result = results.pop().unwrap();
+        results.push(format!(
+            "<{remapped} as {root}WitRemap<{wit}>>::from_wit({result})"
+        ));
+    }
+
+    fn borrows_export_params(&self) -> bool {
+        self.gen.gen.opts.borrowed_export_params && !self.gen.in_import && self.dynamic.is_none()
+    }
+
+    /// Returns whether strings are lifted and lowered as raw code units,
+    /// which `Val`s never are.
+    fn raw_strings(&self) -> bool {
+        self.gen.gen.opts.raw_strings && self.dynamic.is_none()
+    }
+
+    /// Binds the pointer operand of a lifted list or string to a local when
+    /// the lifted value may be borrowed instead, as the pointer is then used
+    /// more than once.
+    fn lifted_param_ptr(&mut self, tmp: usize, operand: &str) -> String {
+        if self.borrows_export_params() {
+            let ptr = format!("ptr{tmp}");
+            self.push_str(&format!("let {ptr} = {operand};\n"));
+            ptr
+        } else {
+            operand.to_string()
+        }
+    }
+
+    /// Lowers the string `operand` with the UTF-16 or latin1+utf16 string
+    /// encoding.
+    fn string_lower_utf16(&mut self, realloc: bool, operand: &str, results: &mut Vec<String>) {
+        let tmp = self.tmp();
+        let ptr = format!("ptr{tmp}");
+        let len = format!("len{tmp}");
+        let rt = self.gen.gen.runtime_path().to_string();
+        let compact = self.gen.gen.opts.string_encoding == StringEncoding::CompactUTF16;
+        if self.raw_strings() {
+            // Raw strings are already UTF-16, which a latin1+utf16 string may
+            // always be encoded as.
+            let val = format!("vec{tmp}");
+            if realloc {
+                self.push_str(&format!("let {val} = ({operand}).into_boxed_slice();\n"));
+            } else {
+                self.push_str(&format!("let {val} = {operand};\n"));
+            }
+            self.push_str(&format!("let {ptr} = {val}.as_ptr() as i32;\n"));
+            if compact {
+                self.push_str(&format!(
+                    "let {len} = {val}.len() as i32 | {rt}::strings::UTF16_TAG;\n"
+                ));
+            } else {
+                self.push_str(&format!("let {len} = {val}.len() as i32;\n"));
+            }
+            if realloc {
+                self.push_str(&format!("::core::mem::forget({val});\n"));
+            }
+        } else {
+            let (lower, layout) = if compact {
+                ("lower_compact_utf16", "compact_utf16_layout")
+            } else {
+                ("lower_utf16", "utf16_layout")
+            };
+            self.push_str(&format!(
+                "let ({ptr}, {len}) = {rt}::strings::{lower}(&{operand});\n"
+            ));
+            if !realloc {
+                // The transcoded copy isn't owned by the callee.
+                self.cleanup.push((
+                    format!("{ptr} as *mut u8"),
+                    format!("{rt}::strings::{layout}({len})"),
+                ));
+            }
+        }
+        results.push(ptr);
+        results.push(len);
+    }
+
+    /// Lifts a string with the UTF-16 or latin1+utf16 string encoding.
+    fn string_lift_utf16(&mut self, operands: &[String], results: &mut Vec<String>) {
+        let tmp = self.tmp();
+        let ptr = self.lifted_param_ptr(tmp, &operands[0]);
+        let len = format!("len{tmp}");
+        let rt = self.gen.gen.runtime_path().to_string();
+        self.push_str(&format!("let {len} = {};\n", operands[1]));
+        let result = match (self.gen.gen.opts.string_encoding, self.raw_strings()) {
+            (StringEncoding::UTF16, true) => {
+                format!("Vec::from_raw_parts({ptr} as *mut u16, {len} as usize, {len} as usize)")
+            }
+            (StringEncoding::UTF16, false) => format!("{rt}::strings::lift_utf16({ptr}, {len})"),
+            (_, true) => format!("{rt}::strings::lift_compact_utf16_raw({ptr}, {len})"),
+            (_, false) => format!("{rt}::strings::lift_compact_utf16({ptr}, {len})"),
+        };
+        if self.borrows_export_params() && self.gen.can_borrow_strings() {
+            self.borrowable.insert(
+                result.clone(),
+                (
+                    format!("::core::slice::from_raw_parts({ptr} as *const u16, {len} as usize)"),
+                    format!("{rt}::dealloc({ptr}, {len} as usize * 2, 2);\n"),
+                ),
+            );
+        }
+        results.push(result);
     }
 
-    fn type_enum(&mut self, id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
-        self.print_typedef_enum(id, name, enum_, docs, &[], Box::new(|_| String::new()));
-    }
+    /// Emits `inst` lifting or lowering `Val`s, or converts its operands so
+    /// that it lowers them as the generated types would be.
+    fn emit_dynamic<'i>(
+        &mut self,
+        inst: &Instruction<'i>,
+        operands: &mut [String],
+        results: &mut Vec<String>,
+    ) -> DynamicEmit<'i> {
+        let dynamic = self.dynamic.as_ref().unwrap();
+        let call = dynamic.call;
+        let func = format!("{:?}", dynamic.func);
+        let rt = self.gen.gen.runtime_path().to_string();
+        let resolve = self.gen.resolve;
+        let expected = |ty: TypeId| format!("{:?}", wit_type_name(resolve, &Type::Id(ty)));
+        // Whether the callee takes ownership of the memory lowered for it.
+        let owned = |realloc: &Option<&str>| realloc.is_some() || call == DynamicCall::Export;
+        let unwrap = |case: &str, ty: &str, op: &str| {
+            format!(
+                "(match {op} {{
+                    {rt}::Val::{case}(v) => v,
+                    v => {rt}::dynamic::mismatch({func}, {ty:?}, v),
+                }})"
+            )
+        };
+        let string = |s: &str| format!("{rt}::string::String::from({s:?})");
+        let boxed = |block: &str| format!("{rt}::boxed::Box::new({block})");
+
+        let (case, ty) = match inst {
+            Instruction::I32FromChar => ("Char", "char"),
+            Instruction::I64FromU64 => ("U64", "u64"),
+            Instruction::I64FromS64 => ("S64", "s64"),
+            Instruction::I32FromU32 => ("U32", "u32"),
+            Instruction::I32FromS32 => ("S32", "s32"),
+            Instruction::I32FromU16 => ("U16", "u16"),
+            Instruction::I32FromS16 => ("S16", "s16"),
+            Instruction::I32FromU8 => ("U8", "u8"),
+            Instruction::I32FromS8 => ("S8", "s8"),
+            Instruction::F32FromFloat32 => ("Float32", "float32"),
+            Instruction::F64FromFloat64 => ("Float64", "float64"),
+            Instruction::I32FromBool => ("Bool", "bool"),
+
+            Instruction::S8FromI32 => return DynamicEmit::Lifted("S8"),
+            Instruction::U8FromI32 => return DynamicEmit::Lifted("U8"),
+            Instruction::S16FromI32 => return DynamicEmit::Lifted("S16"),
+            Instruction::U16FromI32 => return DynamicEmit::Lifted("U16"),
+            Instruction::S32FromI32 => return DynamicEmit::Lifted("S32"),
+            Instruction::U32FromI32 => return DynamicEmit::Lifted("U32"),
+            Instruction::S64FromI64 => return DynamicEmit::Lifted("S64"),
+            Instruction::U64FromI64 => return DynamicEmit::Lifted("U64"),
+            Instruction::CharFromI32 => return DynamicEmit::Lifted("Char"),
+            Instruction::Float32FromF32 => return DynamicEmit::Lifted("Float32"),
+            Instruction::Float64FromF64 => return DynamicEmit::Lifted("Float64"),
+            Instruction::BoolFromI32 => return DynamicEmit::Lifted("Bool"),
+            Instruction::StringLift => return DynamicEmit::Lifted("String"),
+            Instruction::ListLift { .. } => return DynamicEmit::Lifted("List"),
+
+            Instruction::StringLower { realloc }
+                if self.gen.gen.opts.string_encoding == StringEncoding::UTF8 =>
+            {
+                // The string is moved out of its `Val`, so it's kept alive
+                // until the call returns by the cleanup of imports.
+                let tmp = self.tmp();
+                let s = unwrap("String", "string", &operands[0]);
+                uwriteln!(
+                    self.src,
+                    "let vec{tmp} = {s}.into_bytes().into_boxed_slice();
+                    let ptr{tmp} = vec{tmp}.as_ptr() as i32;
+                    let len{tmp} = vec{tmp}.len() as i32;
+                    ::core::mem::forget(vec{tmp});"
+                );
+                if !owned(realloc) {
+                    self.cleanup.push((
+                        format!("ptr{tmp} as *mut u8"),
+                        format!("alloc::Layout::from_size_align_unchecked(len{tmp} as usize, 1)"),
+                    ));
+                }
+                results.push(format!("ptr{tmp}"));
+                results.push(format!("len{tmp}"));
+                return DynamicEmit::Emitted;
+            }
+            Instruction::StringLower { realloc } => {
+                // Other encodings are transcoded to a copy.
+                operands[0] = unwrap("String", "string", &operands[0]);
+                let realloc = owned(realloc).then_some("cabi_realloc");
+                return DynamicEmit::Typed(Some(Instruction::StringLower { realloc }));
+            }
+            Instruction::ListLower { element, realloc } => {
+                let ty = format!("list<{}>", wit_type_name(resolve, element));
+                operands[0] = unwrap("List", &ty, &operands[0]);
+                let realloc = owned(realloc).then_some("cabi_realloc");
+                return DynamicEmit::Typed(Some(Instruction::ListLower { element, realloc }));
+            }
+            Instruction::ListCanonLower { .. } | Instruction::ListCanonLift { .. } => {
+                unreachable!("lists of `Val`s aren't canonical")
+            }
+            Instruction::HandleLower { .. } | Instruction::HandleLift { .. } => {
+                unreachable!("resources can't be passed as `Val`s")
+            }
+
+            Instruction::RecordLower { record, ty, .. } => {
+                let tmp = self.tmp();
+                let fields = (0..record.fields.len())
+                    .map(|i| format!("f{tmp}_{i}"))
+                    .collect::<Vec<_>>();
+                let names = record
+                    .fields
+                    .iter()
+                    .map(|field| format!("{:?}, ", field.name))
+                    .collect::<String>();
+                uwriteln!(
+                    self.src,
+                    "let [{}] = {rt}::dynamic::record({func}, {}, {}, [{names}]);",
+                    fields.join(", "),
+                    expected(*ty),
+                    operands[0],
+                );
+                results.extend(fields);
+                return DynamicEmit::Emitted;
+            }
+            Instruction::RecordLift { record, .. } => {
+                let fields = record
+                    .fields
+                    .iter()
+                    .zip(operands.iter())
+                    .map(|(field, op)| format!("({}, {op}),", string(&field.name)))
+                    .collect::<String>();
+                results.push(format!(
+                    "{rt}::Val::Record({rt}::vec::Vec::from([{fields}]))"
+                ));
+                return DynamicEmit::Emitted;
+            }
+
+            Instruction::TupleLower { tuple, ty } => {
+                let tmp = self.tmp();
+                let elements = (0..tuple.types.len())
+                    .map(|i| format!("t{tmp}_{i}"))
+                    .collect::<Vec<_>>();
+                uwriteln!(
+                    self.src,
+                    "let [{}] = {rt}::dynamic::tuple::<{}>({func}, {}, {});",
+                    elements.join(", "),
+                    elements.len(),
+                    expected(*ty),
+                    operands[0],
+                );
+                results.extend(elements);
+                return DynamicEmit::Emitted;
+            }
+            Instruction::TupleLift { .. } => {
+                results.push(format!(
+                    "{rt}::Val::Tuple({rt}::vec::Vec::from([{}]))",
+                    operands.join(", ")
+                ));
+                return DynamicEmit::Emitted;
+            }
+
+            Instruction::FlagsLower { flags, ty, .. } => {
+                let tmp = self.tmp();
+                let count = flags.repr().count();
+                let names = flags
+                    .flags
+                    .iter()
+                    .map(|flag| format!("{:?}, ", flag.name))
+                    .collect::<String>();
+                uwriteln!(
+                    self.src,
+                    "let flags{tmp} = {rt}::dynamic::flags::<{count}>({func}, {}, {}, &[{names}]);",
+                    expected(*ty),
+                    operands[0],
+                );
+                results.extend((0..count).map(|i| format!("flags{tmp}[{i}]")));
+                return DynamicEmit::Emitted;
+            }
+            Instruction::FlagsLift { flags, .. } => {
+                let names = flags
+                    .flags
+                    .iter()
+                    .map(|flag| format!("{:?}, ", flag.name))
+                    .collect::<String>();
+                results.push(format!(
+                    "{rt}::Val::Flags({rt}::dynamic::flag_names(&[{}], &[{names}]))",
+                    operands.join(", ")
+                ));
+                return DynamicEmit::Emitted;
+            }
+
+            Instruction::VariantLower {
+                variant,
+                results: result_types,
+                ty,
+                ..
+            } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                self.let_results(result_types.len(), results);
+                let cases = variant
+                    .cases
+                    .iter()
+                    .map(|case| format!("({:?}, {}), ", case.name, case.ty.is_some()))
+                    .collect::<String>();
+                uwriteln!(
+                    self.src,
+                    "match {rt}::dynamic::variant({func}, {}, {}, &[{cases}]) {{",
+                    expected(*ty),
+                    operands[0],
+                );
+                for (i, (case, block)) in variant.cases.iter().zip(blocks).enumerate() {
+                    if case.ty.is_some() {
+                        uwriteln!(self.src, "({i}, Some(e)) => {block},");
+                    } else {
+                        uwriteln!(self.src, "({i}, None) => {{\n{block}\n}}");
+                    }
+                }
+                self.push_str("_ => unreachable!(),\n};\n");
+                return DynamicEmit::Emitted;
+            }
+            Instruction::VariantLift { variant, .. } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - variant.cases.len()..)
+                    .collect::<Vec<_>>();
+                let mut result = format!("match {} {{\n", operands[0]);
+                for (i, (case, block)) in variant.cases.iter().zip(blocks).enumerate() {
+                    let payload = match case.ty {
+                        Some(_) => format!("Some({})", boxed(&block)),
+                        None => "None".to_string(),
+                    };
+                    uwriteln!(
+                        result,
+                        "{i} => {rt}::Val::Variant({}, {payload}),",
+                        string(&case.name)
+                    );
+                }
+                result.push_str("_ => panic!(\"invalid enum discriminant\"),\n}");
+                results.push(result);
+                return DynamicEmit::Emitted;
+            }
+
+            Instruction::UnionLower {
+                union,
+                results: result_types,
+                ty,
+                ..
+            } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - union.cases.len()..)
+                    .collect::<Vec<_>>();
+                self.let_results(result_types.len(), results);
+                uwriteln!(
+                    self.src,
+                    "match {rt}::dynamic::union_case({func}, {}, {}, {}) {{",
+                    expected(*ty),
+                    operands[0],
+                    union.cases.len(),
+                );
+                for (i, block) in blocks.into_iter().enumerate() {
+                    uwriteln!(self.src, "({i}, e) => {block},");
+                }
+                self.push_str("_ => unreachable!(),\n};\n");
+                return DynamicEmit::Emitted;
+            }
+            Instruction::UnionLift { union, .. } => {
+                let blocks = self
+                    .blocks
+                    .drain(self.blocks.len() - union.cases.len()..)
+                    .collect::<Vec<_>>();
+                let mut result = format!("match {} {{\n", operands[0]);
+                for (i, block) in blocks.into_iter().enumerate() {
+                    uwriteln!(result, "{i} => {rt}::Val::Union({i}, {}),", boxed(&block));
+                }
+                result.push_str("_ => panic!(\"invalid union discriminant\"),\n}");
+                results.push(result);
+                return DynamicEmit::Emitted;
+            }
 
-    fn type_alias(&mut self, id: TypeId, _name: &str, ty: &Type, docs: &Docs) {
-        self.print_typedef_alias(id, ty, docs);
-    }
+            Instruction::EnumLower { enum_, ty, .. } => {
+                let cases = enum_
+                    .cases
+                    .iter()
+                    .map(|case| format!("{:?}, ", case.name))
+                    .collect::<String>();
+                results.push(format!(
+                    "{rt}::dynamic::enum_case({func}, {}, {}, &[{cases}])",
+                    expected(*ty),
+                    operands[0],
+                ));
+                return DynamicEmit::Emitted;
+            }
+            Instruction::EnumLift { enum_, .. } => {
+                let mut cases = String::new();
+                for (i, case) in enum_.cases.iter().enumerate() {
+                    uwrite!(cases, "{i} => {:?},\n", case.name);
+                }
+                results.push(format!(
+                    "{rt}::Val::Enum({rt}::string::String::from(match {} {{
+                        {cases}
+                        _ => panic!(\"invalid enum discriminant\"),
+                    }}))",
+                    operands[0]
+                ));
+                return DynamicEmit::Emitted;
+            }
 
-    fn type_list(&mut self, id: TypeId, _name: &str, ty: &Type, docs: &Docs) {
-        self.print_type_list(id, ty, docs);
-    }
+            Instruction::OptionLower {
+                results: result_types,
+                ty,
+                ..
+            } => {
+                let some = self.blocks.pop().unwrap();
+                let none = self.blocks.pop().unwrap();
+                self.let_results(result_types.len(), results);
+                uwriteln!(
+                    self.src,
+                    "match {rt}::dynamic::option({func}, {}, {}) {{
+                        Some(e) => {some},
+                        None => {{\n{none}\n}},
+                    }};",
+                    expected(*ty),
+                    operands[0],
+                );
+                return DynamicEmit::Emitted;
+            }
+            Instruction::OptionLift { .. } => {
+                let some = self.blocks.pop().unwrap();
+                let none = self.blocks.pop().unwrap();
+                assert_eq!(none, "()");
+                results.push(format!(
+                    "{rt}::Val::Option(match {} {{
+                        0 => None,
+                        1 => Some({}),
+                        _ => panic!(\"invalid enum discriminant\"),
+                    }})",
+                    operands[0],
+                    boxed(&some),
+                ));
+                return DynamicEmit::Emitted;
+            }
 
-    fn type_builtin(&mut self, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
-        self.rustdoc(docs);
-        self.src
-            .push_str(&format!("pub type {}", name.to_upper_camel_case()));
-        self.src.push_str(" = ");
-        self.print_ty(ty, TypeMode::Owned);
-        self.src.push_str(";\n");
-    }
-}
+            Instruction::ResultLower {
+                result,
+                results: result_types,
+                ty,
+            } => {
+                let err = self.blocks.pop().unwrap();
+                let ok = self.blocks.pop().unwrap();
+                self.let_results(result_types.len(), results);
+                let payload = |ty: &Option<Type>| if ty.is_some() { "Some(e)" } else { "None" };
+                uwriteln!(
+                    self.src,
+                    "match {rt}::dynamic::result({func}, {}, {}, {}, {}) {{
+                        Ok({}) => {{ {ok} }},
+                        Err({}) => {{ {err} }},
+                        _ => unreachable!(),
+                    }};",
+                    expected(*ty),
+                    operands[0],
+                    result.ok.is_some(),
+                    result.err.is_some(),
+                    payload(&result.ok),
+                    payload(&result.err),
+                );
+                return DynamicEmit::Emitted;
+            }
+            Instruction::ResultLift { result, .. } => {
+                let err = self.blocks.pop().unwrap();
+                let ok = self.blocks.pop().unwrap();
+                let payload = |ty: &Option<Type>, block: &str| match ty {
+                    Some(_) => format!("Some({})", boxed(block)),
+                    None => "None".to_string(),
+                };
+                results.push(format!(
+                    "{rt}::Val::Result(match {} {{
+                        0 => Ok({}),
+                        1 => Err({}),
+                        _ => panic!(\"invalid enum discriminant\"),
+                    }})",
+                    operands[0],
+                    payload(&result.ok, &ok),
+                    payload(&result.err, &err),
+                ));
+                return DynamicEmit::Emitted;
+            }
 
-struct FunctionBindgen<'a, 'b> {
-    gen: &'b mut InterfaceGenerator<'a>,
-    params: Vec<String>,
-    trait_name: Option<&'b str>,
-    src: Source,
-    blocks: Vec<String>,
-    block_storage: Vec<(Source, Vec<(String, String)>)>,
-    tmp: usize,
-    needs_cleanup_list: bool,
-    cleanup: Vec<(String, String)>,
-    import_return_pointer_area_size: usize,
-    import_return_pointer_area_align: usize,
-}
+            Instruction::GetArg { nth } if call != DynamicCall::Handler => {
+                results.push(format!("args[{nth}].clone()"));
+                return DynamicEmit::Emitted;
+            }
+            Instruction::CallWasm { sig, .. } if call == DynamicCall::Export => {
+                if sig.retptr {
+                    let (ret, args) = operands.split_last().unwrap();
+                    uwriteln!(self.src, "{ret} = abi({});", args.join(", "));
+                } else if sig.results.is_empty() {
+                    uwriteln!(self.src, "abi({});", operands.join(", "));
+                } else {
+                    uwriteln!(self.src, "let ret = abi({});", operands.join(", "));
+                    results.push("ret".to_string());
+                }
+                return DynamicEmit::Emitted;
+            }
+            Instruction::CallInterface { func: callee } => {
+                let tmp = self.tmp();
+                let amt = callee.results.len();
+                let names = (0..amt)
+                    .map(|i| format!("result{tmp}_{i}"))
+                    .collect::<Vec<_>>();
+                uwriteln!(
+                    self.src,
+                    "let [{}] = {rt}::dynamic::results::<{amt}>(
+                        {func},
+                        <{}Impl as {rt}::dynamic::Handler>::call(
+                            {func},
+                            {rt}::vec::Vec::from([{}]),
+                        ),
+                    );",
+                    names.join(", "),
+                    self.trait_name.unwrap(),
+                    operands.join(", "),
+                );
+                results.extend(names);
+                return DynamicEmit::Emitted;
+            }
+            Instruction::Return { .. } if call != DynamicCall::Handler => {
+                self.emit_cleanup();
+                uwriteln!(self.src, "{rt}::vec::Vec::from([{}])", operands.join(", "));
+                return DynamicEmit::Emitted;
+            }
 
-impl<'a, 'b> FunctionBindgen<'a, 'b> {
-    fn new(
-        gen: &'b mut InterfaceGenerator<'a>,
-        params: Vec<String>,
-        trait_name: Option<&'b str>,
-    ) -> FunctionBindgen<'a, 'b> {
-        FunctionBindgen {
-            gen,
-            params,
-            trait_name,
-            src: Default::default(),
-            blocks: Vec::new(),
-            block_storage: Vec::new(),
-            tmp: 0,
-            needs_cleanup_list: false,
-            cleanup: Vec::new(),
-            import_return_pointer_area_size: 0,
-            import_return_pointer_area_align: 0,
-        }
+            _ => return DynamicEmit::Typed(None),
+        };
+        operands[0] = unwrap(case, ty, &operands[0]);
+        DynamicEmit::Typed(None)
     }
 
     fn emit_cleanup(&mut self) {
@@ -1388,6 +4308,9 @@ impl<'a, 'b> FunctionBindgen<'a, 'b> {
                 "if {layout}.size() != 0 {{\nalloc::dealloc({ptr}, {layout});\n}}\n"
             ));
         }
+        if self.needs_remap_keep {
+            self.push_str("drop(remap_keep);\n");
+        }
         if self.needs_cleanup_list {
             self.push_str(
                 "for (ptr, layout) in cleanup_list {\n
@@ -1467,12 +4390,15 @@ impl Bindgen for FunctionBindgen<'_, '_> {
     fn push_block(&mut self) {
         let prev_src = mem::take(&mut self.src);
         let prev_cleanup = mem::take(&mut self.cleanup);
-        self.block_storage.push((prev_src, prev_cleanup));
+        let prev_uses_cleanup_list = mem::take(&mut self.block_uses_cleanup_list);
+        self.block_storage
+            .push((prev_src, prev_cleanup, prev_uses_cleanup_list));
     }
 
     fn finish_block(&mut self, operands: &mut Vec<String>) {
         if self.cleanup.len() > 0 {
             self.needs_cleanup_list = true;
+            self.block_uses_cleanup_list = true;
             self.push_str("cleanup_list.extend_from_slice(&[");
             for (ptr, layout) in mem::take(&mut self.cleanup) {
                 self.push_str("(");
@@ -1483,9 +4409,13 @@ impl Bindgen for FunctionBindgen<'_, '_> {
             }
             self.push_str("]);\n");
         }
-        let (prev_src, prev_cleanup) = self.block_storage.pop().unwrap();
+        let (prev_src, prev_cleanup, prev_uses_cleanup_list) = self.block_storage.pop().unwrap();
         let src = mem::replace(&mut self.src, prev_src);
         self.cleanup = prev_cleanup;
+        let uses_cleanup_list = self.block_uses_cleanup_list;
+        self.block_uses_cleanup_list = prev_uses_cleanup_list || uses_cleanup_list;
+        self.block_cleanups.truncate(self.blocks.len());
+        self.block_cleanups.push(uses_cleanup_list);
         let expr = match operands.len() {
             0 => "()".to_string(),
             1 => operands[0].clone(),
@@ -1501,7 +4431,38 @@ impl Bindgen for FunctionBindgen<'_, '_> {
     }
 
     fn return_pointer(&mut self, size: usize, align: usize) -> String {
+        // Helpers are passed the memory they read and write.
+        if self.helper.is_some() {
+            return "base".to_string();
+        }
         let tmp = self.tmp();
+        // The parameters of an export shim's `abi` are allocated as it
+        // deallocates them, and its results are where it returns them.
+        if let Some(dynamic) = self
+            .dynamic
+            .as_mut()
+            .filter(|d| d.call == DynamicCall::Export)
+        {
+            if mem::take(&mut dynamic.params_area) {
+                uwriteln!(
+                    self.src,
+                    "let layout{tmp} = alloc::Layout::from_size_align_unchecked({size}, {align});
+                    let ptr{tmp} = alloc::alloc(layout{tmp});
+                    if ptr{tmp}.is_null() {{
+                        alloc::handle_alloc_error(layout{tmp});
+                    }}
+                    let ptr{tmp} = ptr{tmp} as i32;"
+                );
+            } else {
+                uwriteln!(self.src, "let ptr{tmp}: i32;");
+            }
+            return format!("ptr{tmp}");
+        }
+        // The return area of outlined results is still needed by the helper.
+        let src = match &mut self.outlining.discarded {
+            Some((src, ..)) => src,
+            None => &mut self.src,
+        };
 
         // Imports get a per-function return area to facilitate using the
         // stack whereas exports use a per-module return area to cut down on
@@ -1511,11 +4472,18 @@ impl Bindgen for FunctionBindgen<'_, '_> {
             self.import_return_pointer_area_size = self.import_return_pointer_area_size.max(size);
             self.import_return_pointer_area_align =
                 self.import_return_pointer_area_align.max(align);
-            uwrite!(self.src, "let ptr{tmp} = ret_area.as_mut_ptr() as i32;");
+            uwrite!(src, "let ptr{tmp} = ret_area.as_mut_ptr() as i32;");
         } else {
             self.gen.return_pointer_area_size = self.gen.return_pointer_area_size.max(size);
             self.gen.return_pointer_area_align = self.gen.return_pointer_area_align.max(align);
-            uwriteln!(self.src, "let ptr{tmp} = _RET_AREA.0.as_mut_ptr() as i32;");
+            if self.gen.gen.opts.threads {
+                uwriteln!(
+                    src,
+                    "let ptr{tmp} = _RET_AREA.with(|area| area.get() as i32);"
+                );
+            } else {
+                uwriteln!(src, "let ptr{tmp} = _RET_AREA.0.as_mut_ptr() as i32;");
+            }
         }
         format!("ptr{}", tmp)
     }
@@ -1525,7 +4493,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
     }
 
     fn is_list_canonical(&self, resolve: &Resolve, ty: &Type) -> bool {
-        resolve.all_bits_valid(ty)
+        resolve.all_bits_valid(ty) && !self.gen.has_remapped_type(ty) && self.dynamic.is_none()
     }
 
     fn emit(
@@ -1535,6 +4503,29 @@ impl Bindgen for FunctionBindgen<'_, '_> {
         operands: &mut Vec<String>,
         results: &mut Vec<String>,
     ) {
+        let typed;
+        let mut lifted = None;
+        let inst = match self.dynamic {
+            Some(_) => match self.emit_dynamic(inst, operands, results) {
+                DynamicEmit::Emitted => return,
+                DynamicEmit::Typed(Some(inst)) => {
+                    typed = inst;
+                    &typed
+                }
+                DynamicEmit::Typed(None) => inst,
+                DynamicEmit::Lifted(case) => {
+                    lifted = Some(case);
+                    inst
+                }
+            },
+            None => inst,
+        };
+        self.outline_params(inst, operands);
+        let remapped = self.remapped_operand(inst);
+        if let Some((ty, true)) = &remapped {
+            self.lower_remapped(ty, operands);
+        }
+
         let mut top_as = |cvt: &str| {
             let mut s = operands.pop().unwrap();
             s.push_str(" as ");
@@ -1688,14 +4679,29 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                     if let Direction::Export = self.gen.gen.resources[&resource].direction {
                         match handle {
                             Handle::Borrow(_) => {
-                                let name = resolve.types[resource]
-                                    .name
-                                    .as_deref()
-                                    .unwrap()
-                                    .to_upper_camel_case();
+                                let wit_name = resolve.types[resource].name.as_deref().unwrap();
+                                let name = wit_name.to_upper_camel_case();
+                                let rep = if self.gen.gen.opts.mutable_resources {
+                                    format!("::core::cell::RefCell<Rep{name}>")
+                                } else {
+                                    format!("Rep{name}")
+                                };
+                                let interface_name = match resolve.types[resource].owner {
+                                    TypeOwner::Interface(id) => &self.gen.gen.interface_names[&id],
+                                    _ => unreachable!(),
+                                };
+                                let rt = self.gen.gen.runtime_path();
                                 format!(
-                                    "::core::mem::transmute::<isize, &Rep{name}>\
-                                     ({op}.try_into().unwrap())"
+                                    "{{
+                                        {rt}::handles::check(
+                                            \"[export]{interface_name}#{wit_name}\",
+                                            {rt}::handles::Kind::Rep,
+                                            {op},
+                                            \"borrow\",
+                                        );
+                                        ::core::mem::transmute::<isize, &{rep}>\
+                                         ({op}.try_into().unwrap())
+                                    }}"
                                 )
                             }
                             Handle::Own(_) => {
@@ -1998,17 +5004,35 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(len);
             }
 
-            Instruction::ListCanonLift { .. } => {
+            Instruction::ListCanonLift { element, .. } => {
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
+                let ptr = self.lifted_param_ptr(tmp, &operands[0]);
                 self.push_str(&format!("let {} = {} as usize;\n", len, operands[1]));
-                let result = format!(
-                    "Vec::from_raw_parts({} as *mut _, {1}, {1})",
-                    operands[0], len
-                );
+                let result = format!("Vec::from_raw_parts({} as *mut _, {1}, {1})", ptr, len);
+                if self.borrows_export_params() {
+                    let size = self.gen.sizes.size(element);
+                    let align = self.gen.sizes.align(element);
+                    self.borrowable.insert(
+                        result.clone(),
+                        (
+                            format!("::core::slice::from_raw_parts({ptr} as *const _, {len})"),
+                            format!(
+                                "{rt}::dealloc({ptr}, {len} * {size}, {align});\n",
+                                rt = self.gen.gen.runtime_path(),
+                            ),
+                        ),
+                    );
+                }
                 results.push(result);
             }
 
+            Instruction::StringLower { realloc }
+                if self.gen.gen.opts.string_encoding != StringEncoding::UTF8 =>
+            {
+                self.string_lower_utf16(realloc.is_some(), &operands[0], results);
+            }
+
             Instruction::StringLower { realloc } => {
                 let tmp = self.tmp();
                 let val = format!("vec{}", tmp);
@@ -2029,15 +5053,44 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 results.push(len);
             }
 
+            Instruction::StringLift
+                if self.gen.gen.opts.string_encoding != StringEncoding::UTF8 =>
+            {
+                self.string_lift_utf16(operands, results);
+            }
+
             Instruction::StringLift => {
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
+                let ptr = self.lifted_param_ptr(tmp, &operands[0]);
                 self.push_str(&format!("let {} = {} as usize;\n", len, operands[1]));
-                let result = format!(
-                    "Vec::from_raw_parts({} as *mut _, {1}, {1})",
-                    operands[0], len
-                );
-                if self.gen.gen.opts.raw_strings {
+                let result = format!("Vec::from_raw_parts({} as *mut _, {1}, {1})", ptr, len);
+                let borrowed = if self.borrows_export_params() {
+                    let bytes = format!("::core::slice::from_raw_parts({ptr} as *const u8, {len})");
+                    let borrowed = if self.raw_strings() {
+                        bytes
+                    } else {
+                        format!(
+                            "{{\
+                                #[cfg(not(debug_assertions))]\
+                                {{ ::core::str::from_utf8_unchecked({bytes}) }}\
+                                #[cfg(debug_assertions)]\
+                                {{ ::core::str::from_utf8({bytes}).unwrap() }}\
+                            }}"
+                        )
+                    };
+                    let dealloc = format!(
+                        "{rt}::dealloc({ptr}, {len}, 1);\n",
+                        rt = self.gen.gen.runtime_path(),
+                    );
+                    Some((borrowed, dealloc))
+                } else {
+                    None
+                };
+                if self.raw_strings() {
+                    if let Some(borrowed) = borrowed {
+                        self.borrowable.insert(result.clone(), borrowed);
+                    }
                     results.push(result);
                 } else {
                     let mut converted = String::new();
@@ -2054,12 +5107,21 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                     converted.push_str("}");
 
                     converted.push_str("}");
+                    if let Some(borrowed) = borrowed {
+                        self.borrowable.insert(converted.clone(), borrowed);
+                    }
                     results.push(converted);
                 }
             }
 
             Instruction::ListLower { element, realloc } => {
                 let body = self.blocks.pop().unwrap();
+                let kind = if realloc.is_none() {
+                    HelperKind::LowerBorrowed
+                } else {
+                    HelperKind::Lower
+                };
+                let body = self.list_element_body(element, kind, body);
                 let tmp = self.tmp();
                 let vec = format!("vec{tmp}");
                 let result = format!("result{tmp}");
@@ -2101,6 +5163,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
             Instruction::ListLift { element, .. } => {
                 let body = self.blocks.pop().unwrap();
+                let body = self.list_element_body(element, HelperKind::Lift, body);
                 let tmp = self.tmp();
                 let size = self.gen.sizes.size(element);
                 let align = self.gen.sizes.align(element);
@@ -2143,6 +5206,25 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
             Instruction::IterBasePointer => results.push("base".to_string()),
 
+            Instruction::CallWasm { .. } if self.helper.is_some() => {
+                // Helpers lowering values return them, along with the memory
+                // to free once the call returns.
+                if self.helper == Some(HelperKind::LowerFlat) {
+                    if !self.cleanup.is_empty() {
+                        self.needs_cleanup_list = true;
+                        let cleanup = mem::take(&mut self.cleanup)
+                            .into_iter()
+                            .map(|(ptr, layout)| format!("({ptr}, {layout}),"))
+                            .collect::<String>();
+                        uwriteln!(self.src, "cleanup_list.extend_from_slice(&[{cleanup}]);");
+                    }
+                    match operands.len() {
+                        1 => uwriteln!(self.src, "{}", operands[0]),
+                        _ => uwriteln!(self.src, "({})", operands.join(", ")),
+                    }
+                }
+            }
+
             Instruction::CallWasm { name, sig, .. } => {
                 let func = self.declare_import(
                     self.gen.wasm_import_module.unwrap(),
@@ -2162,46 +5244,133 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 self.push_str(");\n");
             }
 
+            Instruction::CallInterface { .. } if self.helper.is_some() => match self.helper {
+                Some(HelperKind::LiftFlat) => uwriteln!(self.src, "{}", operands[0]),
+                _ => results.push("e".to_string()),
+            },
+
             Instruction::CallInterface { func, .. } => {
                 self.let_results(func.results.len(), results);
+                // With `async_exports`, the call is a future to run here.
+                let block_on = if self.gen.gen.opts.async_exports {
+                    format!("{}::executor::block_on(", self.gen.gen.runtime_path())
+                } else {
+                    String::new()
+                };
+                if let FunctionKind::Constructor(ty) = &func.kind {
+                    let camel = resolve.types[*ty]
+                        .name
+                        .as_deref()
+                        .unwrap()
+                        .to_upper_camel_case();
+                    if self.gen.gen.opts.export_macro {
+                        self.push_str(&format!("Own{camel}::new(Rep{camel}::new("));
+                    } else {
+                        self.push_str(&format!("Own{camel}::new("));
+                    }
+                }
+                self.push_str(&block_on);
                 match &func.kind {
+                    FunctionKind::Freestanding if self.gen.gen.opts.export_macro => {
+                        self.push_str(&format!(
+                            "<T as {0}>::{1}",
+                            self.trait_name.unwrap(),
+                            to_rust_ident(&func.name)
+                        ));
+                    }
                     FunctionKind::Freestanding => {
                         self.push_str(&format!(
                             "<{0}Impl as {0}>::{1}",
                             self.trait_name.unwrap(),
                             to_rust_ident(&func.name)
                         ));
+                        if self.gen.gen.opts.singleton {
+                            operands.insert(0, "&mut *instance".to_string());
+                        }
                     }
                     FunctionKind::Method(ty) | FunctionKind::Static(ty) => {
+                        let export_macro = self.gen.gen.opts.export_macro;
+                        if let FunctionKind::Method(_) = &func.kind {
+                            if self.gen.gen.opts.mutable_resources {
+                                operands[0] = format!(
+                                    "&mut *{}.try_borrow_mut().expect(\"resource is already borrowed\")",
+                                    operands[0]
+                                );
+                            }
+                            if export_macro {
+                                let get = if self.gen.gen.opts.mutable_resources {
+                                    "get_mut"
+                                } else {
+                                    "get"
+                                };
+                                operands[0] = format!("({}).{get}::<T>()", operands[0]);
+                            }
+                        }
+                        let camel = resolve.types[*ty]
+                            .name
+                            .as_deref()
+                            .unwrap()
+                            .to_upper_camel_case();
+                        let rep = if export_macro {
+                            "T".to_owned()
+                        } else {
+                            format!("Rep{camel}")
+                        };
                         self.push_str(&format!(
-                            "<Rep{0} as {0}>::{1}",
-                            resolve.types[*ty]
-                                .name
-                                .as_deref()
-                                .unwrap()
-                                .to_upper_camel_case(),
+                            "<{rep} as {camel}>::{}",
                             to_rust_ident(func.item_name())
                         ));
                     }
                     FunctionKind::Constructor(ty) => {
                         self.gen.mark_resource_owned(*ty);
-                        self.push_str(&format!(
-                            "Own{0}::new(<Rep{0} as {0}>::new",
-                            resolve.types[*ty]
-                                .name
-                                .as_deref()
-                                .unwrap()
-                                .to_upper_camel_case()
-                        ));
+                        let camel = resolve.types[*ty]
+                            .name
+                            .as_deref()
+                            .unwrap()
+                            .to_upper_camel_case();
+                        if self.gen.gen.opts.export_macro {
+                            self.push_str(&format!("<T as {camel}>::new"));
+                        } else {
+                            self.push_str(&format!("<Rep{camel} as {camel}>::new"));
+                        }
+                    }
+                }
+                let mut deallocs = Vec::new();
+                if self.borrows_export_params() {
+                    for (operand, (_, ty)) in operands.iter_mut().zip(&func.params) {
+                        if !is_borrowable_param(resolve, ty, self.gen.can_borrow_strings()) {
+                            continue;
+                        }
+                        if let Some((borrowed, dealloc)) = self.borrowable.remove(operand) {
+                            *operand = borrowed;
+                            deallocs.push(dealloc);
+                        }
                     }
                 }
                 self.push_str("(");
                 self.push_str(&operands.join(", "));
                 self.push_str(")");
+                if !block_on.is_empty() {
+                    self.push_str(")");
+                }
                 if let FunctionKind::Constructor(_) = &func.kind {
                     self.push_str(")");
+                    if self.gen.gen.opts.export_macro {
+                        self.push_str(")");
+                    }
                 }
+                let convert_error = self.gen.convert_error(func);
+                self.push_str(&convert_error);
                 self.push_str(";\n");
+                for dealloc in deallocs {
+                    self.push_str(&dealloc);
+                }
+            }
+
+            Instruction::Return { .. } if self.helper.is_some() => {
+                if self.helper == Some(HelperKind::Lift) {
+                    uwriteln!(self.src, "{}", operands[0]);
+                }
             }
 
             Instruction::Return { amt, .. } => {
@@ -2304,12 +5473,18 @@ impl Bindgen for FunctionBindgen<'_, '_> {
             }
 
             Instruction::GuestDeallocateString => {
-                self.push_str(&format!(
-                    "{rt}::dealloc({op0}, ({op1}) as usize, 1);\n",
-                    rt = self.gen.gen.runtime_path(),
-                    op0 = operands[0],
-                    op1 = operands[1],
-                ));
+                let rt = self.gen.gen.runtime_path();
+                let op0 = &operands[0];
+                let op1 = &operands[1];
+                self.push_str(&match self.gen.gen.opts.string_encoding {
+                    StringEncoding::UTF8 => format!("{rt}::dealloc({op0}, ({op1}) as usize, 1);\n"),
+                    StringEncoding::UTF16 => {
+                        format!("{rt}::dealloc({op0}, ({op1}) as usize * 2, 2);\n")
+                    }
+                    StringEncoding::CompactUTF16 => {
+                        format!("{rt}::strings::dealloc_compact_utf16({op0}, {op1});\n")
+                    }
+                });
             }
 
             Instruction::GuestDeallocateVariant { blocks } => {
@@ -2333,6 +5508,7 @@ impl Bindgen for FunctionBindgen<'_, '_> {
 
             Instruction::GuestDeallocateList { element } => {
                 let body = self.blocks.pop().unwrap();
+                let body = self.list_element_body(element, HelperKind::Dealloc, body);
                 let tmp = self.tmp();
                 let size = self.gen.sizes.size(element);
                 let align = self.gen.sizes.align(element);
@@ -2365,6 +5541,182 @@ impl Bindgen for FunctionBindgen<'_, '_> {
                 ));
             }
         }
+
+        if let Some((ty, false)) = &remapped {
+            self.lift_remapped(ty, results);
+        }
+        if let Some(case) = lifted {
+            let result = results.pop().unwrap();
+            results.push(format!(
+                "{}::Val::{case}({result})",
+                self.gen.gen.runtime_path()
+            ));
+        }
+        self.outline_result(inst, operands, results);
+    }
+}
+
+fn reflect_docs(docs: &Docs) -> String {
+    match &docs.contents {
+        Some(contents) => format!("Some({:?})", contents.trim_end()),
+        None => "None".to_string(),
+    }
+}
+
+fn reflect_function(resolve: &Resolve, rt: &str, func: &Function) -> String {
+    let value = |name: Option<&str>, ty: &Type| {
+        format!(
+            "{rt}::reflect::Value {{ name: {name:?}, ty: {:?} }},",
+            wit_type_name(resolve, ty)
+        )
+    };
+    let params = func
+        .params
+        .iter()
+        .map(|(name, ty)| value(Some(name), ty))
+        .collect::<String>();
+    let results = match &func.results {
+        Results::Named(results) => results
+            .iter()
+            .map(|(name, ty)| value(Some(name), ty))
+            .collect::<String>(),
+        Results::Anon(ty) => value(None, ty),
+    };
+    format!(
+        "{rt}::reflect::Function {{
+            name: {:?},
+            docs: {},
+            params: &[{params}],
+            results: &[{results}],
+        }},",
+        func.name,
+        reflect_docs(&func.docs),
+    )
+}
+
+fn reflect_resource(resolve: &Resolve, rt: &str, id: TypeId) -> Option<String> {
+    let ty = &resolve.types[id];
+    if !matches!(ty.kind, TypeDefKind::Resource) {
+        return None;
+    }
+    Some(format!(
+        "{rt}::reflect::Resource {{ name: {:?}, docs: {} }},",
+        ty.name.as_deref().unwrap(),
+        reflect_docs(&ty.docs),
+    ))
+}
+
+/// Returns whether `func` takes or returns resources.
+fn uses_resources(resolve: &Resolve, func: &Function) -> bool {
+    let mut types = Types::default();
+    func.params
+        .iter()
+        .map(|(_, ty)| ty)
+        .chain(func.results.iter_types())
+        .any(|ty| types.type_info(resolve, ty).has_resource)
+}
+
+/// Returns the core wasm types `ty` is flattened to when passed as a
+/// parameter, or a single pointer if it doesn't fit in the flat parameters.
+fn flat_types(resolve: &Resolve, variant: AbiVariant, ty: &Type) -> Vec<WasmType> {
+    let func = Function {
+        docs: Docs::default(),
+        name: String::new(),
+        kind: FunctionKind::Freestanding,
+        params: vec![(String::new(), *ty)],
+        results: Results::Named(Vec::new()),
+    };
+    resolve.wasm_signature(variant, &func).params
+}
+
+/// Finds the type named `path` in `world`, either a type defined by the world
+/// itself or `<interface>/<name>` for a type of one of its interfaces.
+fn find_world_type(resolve: &Resolve, world: WorldId, path: &str) -> Option<TypeId> {
+    let world = &resolve.worlds[world];
+    let (interface, name) = match path.rsplit_once('/') {
+        Some((interface, name)) => (Some(interface), name),
+        None => (None, path),
+    };
+    world
+        .imports
+        .iter()
+        .chain(world.exports.iter())
+        .find_map(|(key, item)| match (interface, item) {
+            (None, WorldItem::Type(id)) if *key == WorldKey::Name(name.to_string()) => Some(*id),
+            (Some(interface), WorldItem::Interface(id))
+                if resolve.name_world_key(key) == interface =>
+            {
+                resolve.interfaces[*id].types.get(name).copied()
+            }
+            _ => None,
+        })
+}
+
+/// Returns how `ty` is written in WIT, where named types are referred to by
+/// their unqualified name.
+fn wit_type_name(resolve: &Resolve, ty: &Type) -> String {
+    let id = match ty {
+        Type::Bool => return "bool".to_string(),
+        Type::U8 => return "u8".to_string(),
+        Type::U16 => return "u16".to_string(),
+        Type::U32 => return "u32".to_string(),
+        Type::U64 => return "u64".to_string(),
+        Type::S8 => return "s8".to_string(),
+        Type::S16 => return "s16".to_string(),
+        Type::S32 => return "s32".to_string(),
+        Type::S64 => return "s64".to_string(),
+        Type::Float32 => return "float32".to_string(),
+        Type::Float64 => return "float64".to_string(),
+        Type::Char => return "char".to_string(),
+        Type::String => return "string".to_string(),
+        Type::Id(id) => *id,
+    };
+    let ty = &resolve.types[id];
+    if let Some(name) = &ty.name {
+        return name.clone();
+    }
+    wit_kind_name(resolve, &ty.kind)
+}
+
+/// Returns the name of an anonymous type of kind `kind` in WIT.
+fn wit_kind_name(resolve: &Resolve, kind: &TypeDefKind) -> String {
+    let optional = |ty: &Option<Type>| match ty {
+        Some(ty) => wit_type_name(resolve, ty),
+        None => "_".to_string(),
+    };
+    match kind {
+        TypeDefKind::Type(ty) => wit_type_name(resolve, ty),
+        TypeDefKind::List(ty) => format!("list<{}>", wit_type_name(resolve, ty)),
+        TypeDefKind::Option(ty) => format!("option<{}>", wit_type_name(resolve, ty)),
+        TypeDefKind::Result(r) => match (&r.ok, &r.err) {
+            (None, None) => "result".to_string(),
+            (Some(ok), None) => format!("result<{}>", wit_type_name(resolve, ok)),
+            (ok, Some(err)) => format!("result<{}, {}>", optional(ok), wit_type_name(resolve, err)),
+        },
+        TypeDefKind::Tuple(t) => format!(
+            "tuple<{}>",
+            t.types
+                .iter()
+                .map(|ty| wit_type_name(resolve, ty))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        TypeDefKind::Handle(Handle::Own(id)) => wit_type_name(resolve, &Type::Id(*id)),
+        TypeDefKind::Handle(Handle::Borrow(id)) => {
+            format!("borrow<{}>", wit_type_name(resolve, &Type::Id(*id)))
+        }
+        TypeDefKind::Future(ty) => match ty {
+            Some(ty) => format!("future<{}>", wit_type_name(resolve, ty)),
+            None => "future".to_string(),
+        },
+        TypeDefKind::Stream(s) => format!("stream<{}, {}>", optional(&s.element), optional(&s.end)),
+        TypeDefKind::Record(_)
+        | TypeDefKind::Resource
+        | TypeDefKind::Flags(_)
+        | TypeDefKind::Enum(_)
+        | TypeDefKind::Variant(_)
+        | TypeDefKind::Union(_)
+        | TypeDefKind::Unknown => unreachable!(),
     }
 }
 
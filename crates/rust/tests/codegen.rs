@@ -1,6 +1,41 @@
 #![allow(unused_macros)]
 
 mod codegen_tests {
+    // `serde` rejects these tests' records containing resources.
+    macro_rules! serde_codegen_test {
+        (resource_alias $test:tt) => {};
+        (resource_borrow_in_record $test:tt) => {};
+        (resource_borrow_in_record_export $test:tt) => {};
+        ($id:ident $test:tt) => {
+            mod serde {
+                wit_bindgen::generate!({
+                    path: $test,
+                    stubs,
+                    serde,
+                    export_prefix: "[serde]",
+                });
+
+                #[test]
+                fn works() {}
+            }
+
+            mod serde_borrowing {
+                wit_bindgen::generate!({
+                    path: $test,
+                    stubs,
+                    serde,
+                    ownership: Borrowing {
+                        duplicate_if_necessary: true
+                    },
+                    export_prefix: "[serde-borrowing]",
+                });
+
+                #[test]
+                fn works() {}
+            }
+        };
+    }
+
     macro_rules! codegen_test {
         ($id:ident $name:tt $test:tt) => {
             mod $id {
@@ -31,11 +66,304 @@ mod codegen_tests {
                     #[test]
                     fn works() {}
                 }
+
+                mod outline_helpers {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        outline_helpers,
+                        export_prefix: "[outline]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod threads {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        threads,
+                        export_prefix: "[threads]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod borrowed_export_params {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        borrowed_export_params,
+                        export_prefix: "[borrowed-export-params]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod export_macro {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        export_macro,
+                        export_prefix: "[export-macro]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod mutable_resources {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        mutable_resources,
+                        export_prefix: "[mutable-resources]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod utf16 {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        string_encoding: "utf16",
+                        export_prefix: "[utf16]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod compact_utf16_raw {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        raw_strings,
+                        string_encoding: "compact-utf16",
+                        export_prefix: "[compact-utf16-raw]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod reflection {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        reflection,
+                        export_prefix: "[reflection]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod dispatch {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        dispatch,
+                        export_prefix: "[dispatch]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod dispatch_borrowing {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        dispatch,
+                        borrowed_export_params,
+                        ownership: Borrowing {
+                            duplicate_if_necessary: true
+                        },
+                        export_prefix: "[dispatch-borrowing]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                serde_codegen_test!($id $test);
+
+                mod instrument {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        instrument,
+                        export_prefix: "[instrument]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod error_conversion {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        error_conversion,
+                        dispatch,
+                        export_prefix: "[error-conversion]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod singleton {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        singleton,
+                        export_prefix: "[singleton]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod async_exports {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        async_exports,
+                        export_prefix: "[async-exports]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod case_conversions {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        case_conversions,
+                        export_prefix: "[case-conversions]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod record_builders {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        record_builders,
+                        export_prefix: "[record-builders]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
+
+                mod remap_types {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        stubs,
+                        remap_types: {
+                            "string": crate::codegen_tests::$id::remap_types::Text,
+                        },
+                        export_prefix: "[remap-types]",
+                    });
+
+                    #[derive(Clone, Debug)]
+                    #[allow(dead_code)]
+                    pub struct Text(String);
+
+                    impl WitRemap<String> for Text {
+                        fn from_wit(wit: String) -> Self {
+                            Text(wit)
+                        }
+
+                        fn to_wit(&self) -> String {
+                            self.0.clone()
+                        }
+                    }
+
+                    #[test]
+                    fn works() {}
+                }
+
+                #[test]
+                fn split_modules() {
+                    test_helpers::run_world_codegen_test(
+                        "guest-rust-split-modules",
+                        $test.as_ref(),
+                        |resolve, world, files| {
+                            let mut opts = wit_bindgen_rust::Opts::default();
+                            opts.stubs = true;
+                            opts.split_modules = true;
+                            opts.build().generate(resolve, world, files).unwrap()
+                        },
+                        super::verify_split_modules,
+                    );
+                }
             }
 
         };
     }
     test_helpers::codegen_tests!();
+
+    /// Checks that every `pub mod foo;` in the tree of `mod.rs` files rooted at
+    /// `<world>/mod.rs` refers to a generated file, and that every generated
+    /// file is reachable from the root.
+    fn verify_split_modules(dir: &std::path::Path, name: &str) {
+        use heck::ToSnakeCase;
+
+        fn visit(file: &std::path::Path, seen: &mut Vec<std::path::PathBuf>) {
+            seen.push(file.to_path_buf());
+            let contents = std::fs::read_to_string(file).unwrap();
+            for line in contents.lines() {
+                let Some(module) = line
+                    .trim()
+                    .strip_prefix("pub mod ")
+                    .and_then(|m| m.strip_suffix(';'))
+                else {
+                    continue;
+                };
+                let dir = file.parent().unwrap();
+                let leaf = dir.join(format!("{module}.rs"));
+                let nested = dir.join(module).join("mod.rs");
+                if leaf.exists() {
+                    seen.push(leaf);
+                } else if nested.exists() {
+                    visit(&nested, seen);
+                } else {
+                    panic!("module `{module}` declared in {file:?} was not generated");
+                }
+            }
+        }
+
+        let root = dir.join(name.to_snake_case());
+        let mut seen = Vec::new();
+        visit(&root.join("mod.rs"), &mut seen);
+
+        fn count(dir: &std::path::Path) -> usize {
+            std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| {
+                    let path = entry.unwrap().path();
+                    if path.is_dir() {
+                        count(&path)
+                    } else {
+                        1
+                    }
+                })
+                .sum()
+        }
+        assert_eq!(seen.len(), count(&root));
+    }
 }
 
 mod strings {
@@ -195,3 +523,1817 @@ mod alternative_runtime_path {
         fn foobar() {}
     }
 }
+
+mod export_macro {
+    mod bindings {
+        wit_bindgen::generate!({
+            inline: "
+                package my:export-macro
+
+                interface strings {
+                    concat: func(a: string, b: string) -> string
+                }
+
+                interface counters {
+                    resource counter {
+                        constructor(start: u32)
+                        get: func() -> u32
+                        add: func(other: borrow<counter>) -> u32
+                    }
+
+                    total: func(counters: list<borrow<counter>>) -> u32
+                }
+
+                world registered {
+                    export strings
+                    export counters
+                    export registered-func: func() -> u32
+                }
+            ",
+            export_macro,
+        });
+    }
+
+    use bindings::exports::my::export_macro::counters::{self, RepCounter};
+
+    struct Component;
+
+    impl bindings::Registered for Component {
+        fn registered_func() -> u32 {
+            0
+        }
+    }
+
+    impl bindings::exports::my::export_macro::strings::Strings for Component {
+        fn concat(a: String, b: String) -> String {
+            a + &b
+        }
+    }
+
+    impl counters::Counters for Component {
+        type Counter = MyCounter;
+
+        fn total(counters: Vec<&RepCounter>) -> u32 {
+            counters.iter().map(|c| c.get::<MyCounter>().0).sum()
+        }
+    }
+
+    struct MyCounter(u32);
+
+    impl counters::Counter for MyCounter {
+        fn new(start: u32) -> Self {
+            MyCounter(start)
+        }
+
+        fn get(&self) -> u32 {
+            self.0
+        }
+
+        fn add(&self, other: &RepCounter) -> u32 {
+            self.0 + other.get::<MyCounter>().0
+        }
+    }
+
+    bindings::export!(self::Component, with_types_in bindings);
+
+    #[test]
+    fn reps_hold_the_registered_type() {
+        let rep = RepCounter::new(MyCounter(2));
+        assert_eq!(rep.get::<MyCounter>().0, 2);
+        assert_eq!(
+            <Component as counters::Counters>::total(vec![&rep, &rep]),
+            4
+        );
+    }
+
+    #[test]
+    fn rejects_exports() {
+        use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(
+                UnresolvedPackage::parse(
+                    "registered.wit".as_ref(),
+                    "
+                        package my:export-macro
+
+                        world registered {
+                            export registered-func: func() -> u32
+                        }
+                    ",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let mut opts = wit_bindgen_rust::Opts {
+            export_macro: true,
+            ..Default::default()
+        };
+        opts.exports
+            .insert(wit_bindgen_rust::ExportKey::World, "Component".to_string());
+        let err = opts
+            .build()
+            .generate(&resolve, world, &mut wit_bindgen_core::Files::default())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("`exports` entries `world` are unused with `export_macro`"),
+            "{err}"
+        );
+    }
+}
+
+mod mutable_resources {
+    wit_bindgen::generate!({
+        inline: "
+            package my:mutable-resources
+
+            interface counters {
+                resource counter {
+                    constructor(start: u32)
+                    increment: func() -> u32
+                    add: func(other: borrow<counter>)
+                }
+            }
+
+            world mutable-resources {
+                export counters
+            }
+        ",
+        mutable_resources,
+        exports: {
+            "my:mutable-resources/counters/counter": Counter,
+        }
+    });
+
+    pub struct Counter {
+        value: u32,
+    }
+
+    impl exports::my::mutable_resources::counters::Counter for Counter {
+        fn new(start: u32) -> Self {
+            Self { value: start }
+        }
+
+        fn increment(&mut self) -> u32 {
+            self.value += 1;
+            self.value
+        }
+
+        fn add(&mut self, other: &::core::cell::RefCell<Counter>) {
+            self.value += other.borrow().value;
+        }
+    }
+}
+
+mod handle_tracking {
+    use wit_bindgen::rt::handles::{acquire, check, release, Kind};
+
+    #[test]
+    #[should_panic(expected = "`drop` of `my:handles/foo#bar` Own handle 1: handle is not live")]
+    fn double_drop() {
+        acquire("my:handles/foo#bar", Kind::Own, 1, "from_handle");
+        acquire("my:handles/foo#bar", Kind::Borrow, 2, "from_handle");
+        acquire("my:handles/foo#bar", Kind::Borrow, 2, "from_handle");
+        release("my:handles/foo#bar", Kind::Borrow, 2, "drop");
+        release("my:handles/foo#bar", Kind::Borrow, 2, "drop");
+        release("my:handles/foo#bar", Kind::Own, 1, "drop");
+        release("my:handles/foo#bar", Kind::Own, 1, "drop");
+    }
+
+    #[test]
+    #[should_panic(expected = "`borrow` of `my:handles/foo#baz` Rep handle 3: handle is not live")]
+    fn borrow_after_dtor() {
+        acquire("my:handles/foo#baz", Kind::Rep, 3, "new");
+        check("my:handles/foo#baz", Kind::Rep, 3, "borrow");
+        release("my:handles/foo#baz", Kind::Rep, 3, "dtor");
+        check("my:handles/foo#baz", Kind::Rep, 3, "borrow");
+    }
+
+    #[test]
+    fn concurrent_threads() {
+        let threads = (0..8)
+            .map(|thread| {
+                std::thread::spawn(move || {
+                    for handle in 0..1000 {
+                        let handle = thread * 1000 + handle;
+                        acquire("my:handles/foo#threads", Kind::Own, handle, "new");
+                        check("my:handles/foo#threads", Kind::Own, handle, "get");
+                        release("my:handles/foo#threads", Kind::Own, handle, "drop");
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
+
+mod reflection {
+    wit_bindgen::generate!({
+        inline: "
+            package my:reflection
+
+            interface shapes {
+                /// A drawable shape.
+                resource shape {
+                    area: func() -> float64
+                }
+
+                /// Draws `shapes`, returning how many were drawn.
+                draw: func(shapes: list<borrow<shape>>, color: option<u32>) -> result<u32, string>
+            }
+
+            /// Routes requests to shapes.
+            world router {
+                import log: func(msg: string)
+                export shapes
+            }
+        ",
+        stubs,
+        reflection,
+    });
+
+    use wit_bindgen::rt::reflect::{Item, Value};
+
+    #[test]
+    fn describes_world() {
+        assert_eq!(WORLD.name, "router");
+        assert_eq!(WORLD.docs, Some("Routes requests to shapes."));
+
+        let [Item::Function(log)] = WORLD.imports else {
+            panic!("unexpected imports: {:?}", WORLD.imports);
+        };
+        assert_eq!(log.name, "log");
+        assert!(log.results.is_empty());
+
+        assert!(WORLD.import("my:reflection/shapes").is_none());
+        let shapes = WORLD.export("my:reflection/shapes").unwrap();
+        assert_eq!(shapes.resources.len(), 1);
+        assert_eq!(shapes.resources[0].name, "shape");
+        assert_eq!(shapes.resources[0].docs, Some("A drawable shape."));
+
+        let area = shapes.function("[method]shape.area").unwrap();
+        assert_eq!(area.params[0].ty, "borrow<shape>");
+
+        let draw = shapes.function("draw").unwrap();
+        assert_eq!(
+            draw.docs,
+            Some("Draws `shapes`, returning how many were drawn.")
+        );
+        let types = draw
+            .params
+            .iter()
+            .map(|p| (p.name, p.ty))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                (Some("shapes"), "list<borrow<shape>>"),
+                (Some("color"), "option<u32>"),
+            ]
+        );
+        let [Value { name: None, ty }] = draw.results else {
+            panic!("unexpected results: {:?}", draw.results);
+        };
+        assert_eq!(*ty, "result<u32, string>");
+    }
+}
+
+mod dispatch {
+    // Values in memory are addressed by 32-bit pointers, so only calls passing
+    // their values as flat parameters and results work off wasm.
+    wit_bindgen::generate!({
+        inline: "
+            package my:dispatch
+
+            interface shapes {
+                record point {
+                    x: s32,
+                    y: s32,
+                }
+
+                enum color {
+                    red,
+                    green,
+                }
+
+                variant shade {
+                    solid(color),
+                    gradient(tuple<color, color>),
+                    none,
+                }
+
+                flags style {
+                    bold,
+                    dashed,
+                }
+
+                length: func(p: point) -> u32
+                first: func(s: shade, fallback: color) -> color
+                toggle: func(s: style, bold: bool) -> style
+                pick: func(a: option<s64>, b: float64) -> float64
+            }
+
+            world renderer {
+                import shapes
+                export shapes
+            }
+        ",
+        exports: {
+            "my:dispatch/shapes": Shapes,
+        },
+        dispatch,
+        export_prefix: "[dispatch-tests]",
+    });
+
+    use exports::my::dispatch::shapes::{Color, Point, Shade, Style};
+    use wit_bindgen::rt::Val;
+
+    pub struct Shapes;
+
+    impl exports::my::dispatch::shapes::Shapes for Shapes {
+        fn length(p: Point) -> u32 {
+            p.x.unsigned_abs() + p.y.unsigned_abs()
+        }
+
+        fn first(s: Shade, fallback: Color) -> Color {
+            match s {
+                Shade::Solid(color) | Shade::Gradient((color, _)) => color,
+                Shade::None => fallback,
+            }
+        }
+
+        fn toggle(s: Style, bold: bool) -> Style {
+            if bold {
+                s ^ Style::BOLD
+            } else {
+                s ^ Style::DASHED
+            }
+        }
+
+        fn pick(a: Option<i64>, b: f64) -> f64 {
+            a.map_or(b, |a| a as f64)
+        }
+    }
+
+    fn point(x: i32, y: i32) -> Val {
+        Val::Record(vec![
+            ("x".to_string(), Val::S32(x)),
+            ("y".to_string(), Val::S32(y)),
+        ])
+    }
+
+    fn color(name: &str) -> Val {
+        Val::Enum(name.to_string())
+    }
+
+    #[test]
+    fn calls_exports() {
+        let length = DISPATCH.export("my:dispatch/shapes#length").unwrap();
+        assert_eq!(length(&[point(3, -4)]), [Val::U32(7)]);
+
+        let first = DISPATCH.export("my:dispatch/shapes#first").unwrap();
+        let gradient = Val::Tuple(vec![color("green"), color("red")]);
+        let shade = Val::Variant("gradient".to_string(), Some(Box::new(gradient)));
+        assert_eq!(first(&[shade, color("red")]), [color("green")]);
+        let shade = Val::Variant("none".to_string(), None);
+        assert_eq!(first(&[shade, color("red")]), [color("red")]);
+
+        let toggle = DISPATCH.export("my:dispatch/shapes#toggle").unwrap();
+        let style = Val::Flags(vec!["dashed".to_string()]);
+        assert_eq!(
+            toggle(&[style, Val::Bool(true)]),
+            [Val::Flags(vec!["bold".to_string(), "dashed".to_string()])]
+        );
+
+        let pick = DISPATCH.export("my:dispatch/shapes#pick").unwrap();
+        let some = Val::Option(Some(Box::new(Val::S64(-2))));
+        assert_eq!(pick(&[some, Val::Float64(0.5)]), [Val::Float64(-2.0)]);
+        assert_eq!(
+            pick(&[Val::Option(None), Val::Float64(0.5)]),
+            [Val::Float64(0.5)]
+        );
+    }
+
+    #[test]
+    fn imports_and_exports_are_distinct() {
+        let names = DISPATCH.imports.iter().map(|(name, _)| *name);
+        assert!(names.eq([
+            "my:dispatch/shapes#length",
+            "my:dispatch/shapes#first",
+            "my:dispatch/shapes#toggle",
+            "my:dispatch/shapes#pick",
+        ]));
+        let _: [wit_bindgen::rt::dynamic::Shim; 2] = [
+            my::dispatch::shapes::__dispatch_import_length,
+            exports::my::dispatch::shapes::__dispatch_export_length,
+        ];
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "`my:dispatch/shapes#first` expected a value of type `color`, found U32(1)"
+    )]
+    fn wrong_argument() {
+        let first = DISPATCH.export("my:dispatch/shapes#first").unwrap();
+        first(&[Val::Variant("none".to_string(), None), Val::U32(1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "`my:dispatch/shapes#length` takes 1 arguments but 2 were given")]
+    fn wrong_arity() {
+        let length = DISPATCH.export("my:dispatch/shapes#length").unwrap();
+        length(&[point(0, 0), point(0, 0)]);
+    }
+}
+
+mod dynamic_exports {
+    use std::sync::Mutex;
+    use wit_bindgen::rt::Val;
+
+    wit_bindgen::generate!({
+        inline: "
+            package my:proxy
+
+            interface counter {
+                enum step {
+                    one,
+                    ten,
+                }
+
+                add: func(n: u32, step: step) -> u32
+                reset: func()
+            }
+
+            world proxy {
+                export counter
+            }
+        ",
+        exports: {
+            "my:proxy/counter": Logger,
+        },
+        dispatch,
+        dynamic_exports,
+        export_prefix: "[dynamic-exports]",
+    });
+
+    static CALLS: Mutex<Vec<(String, Vec<Val>)>> = Mutex::new(Vec::new());
+
+    pub struct Logger;
+
+    impl wit_bindgen::rt::dynamic::Handler for Logger {
+        fn call(func: &str, args: Vec<Val>) -> Vec<Val> {
+            CALLS.lock().unwrap().push((func.to_string(), args.clone()));
+            match (func, &args[..]) {
+                ("my:proxy/counter#add", [Val::U32(n), Val::Enum(step)]) => {
+                    vec![Val::U32(n + if step == "ten" { 10 } else { 1 })]
+                }
+                ("my:proxy/counter#reset", []) => Vec::new(),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn generate(wit: &str, opts: wit_bindgen_rust::Opts) -> anyhow::Result<()> {
+        use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(UnresolvedPackage::parse("proxy.wit".as_ref(), wit).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        opts.build()
+            .generate(&resolve, world, &mut wit_bindgen_core::Files::default())
+    }
+
+    #[test]
+    fn rejects_trait_only_options() {
+        let wit = "
+            package my:proxy
+
+            world proxy {
+                export run: func()
+            }
+        ";
+        for singleton in [false, true] {
+            let opts = wit_bindgen_rust::Opts {
+                dispatch: true,
+                stubs: true,
+                singleton,
+                export_macro: !singleton,
+                ..Default::default()
+            };
+            let err = generate(wit, opts).unwrap_err().to_string();
+            assert!(
+                err.contains("the `dispatch` option can't be used with"),
+                "{err}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_resources() {
+        let wit = "
+            package my:proxy
+
+            interface files {
+                resource file
+
+                open: func(path: string) -> own<file>
+            }
+
+            world proxy {
+                export files
+            }
+        ";
+        let opts = wit_bindgen_rust::Opts {
+            dynamic_exports: true,
+            stubs: true,
+            ..Default::default()
+        };
+        let err = generate(wit, opts).unwrap_err().to_string();
+        assert!(
+            err.contains("can't implement the export `my:proxy/files`"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn forwards_calls() {
+        let add = DISPATCH.export("my:proxy/counter#add").unwrap();
+        let step = Val::Enum("ten".to_string());
+        assert_eq!(add(&[Val::U32(5), step.clone()]), [Val::U32(15)]);
+
+        extern "C" {
+            #[link_name = "[dynamic-exports]my:proxy/counter#reset"]
+            fn reset();
+        }
+        unsafe { reset() };
+
+        assert_eq!(
+            *CALLS.lock().unwrap(),
+            [
+                ("my:proxy/counter#add".to_string(), vec![Val::U32(5), step]),
+                ("my:proxy/counter#reset".to_string(), vec![]),
+            ]
+        );
+    }
+}
+
+mod serde {
+    wit_bindgen::generate!({
+        inline: "
+            package my:serde
+
+            interface config {
+                enum log-level {
+                    info,
+                    very-verbose,
+                }
+
+                flags features {
+                    fast-path,
+                    tracing,
+                }
+
+                variant backend {
+                    in-memory,
+                    on-disk(string),
+                }
+
+                union address {
+                    u16,
+                    string,
+                }
+
+                record server-config {
+                    listen-port: u16,
+                    log-level: log-level,
+                    features: features,
+                    backends: list<backend>,
+                }
+
+                load: func() -> server-config
+                connect: func(to: address)
+            }
+
+            world server {
+                import config
+            }
+        ",
+        serde,
+    });
+
+    use my::serde::config::{Address, Backend, Features, LogLevel, ServerConfig};
+
+    #[test]
+    fn uses_wit_names() {
+        let config = ServerConfig {
+            listen_port: 8080,
+            log_level: LogLevel::VeryVerbose,
+            features: Features::FAST_PATH | Features::TRACING,
+            backends: vec![Backend::InMemory, Backend::OnDisk("/tmp".to_string())],
+        };
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"listen-port":8080,"log-level":"very-verbose","features":["fast-path","tracing"],"backends":["in-memory",{"on-disk":"/tmp"}]}"#
+        );
+
+        let config: ServerConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(config.features, Features::FAST_PATH | Features::TRACING);
+        assert!(matches!(&config.backends[1], Backend::OnDisk(path) if path == "/tmp"));
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        let err = serde_json::from_str::<Features>(r#"["fast-path","slow-path"]"#).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `slow-path`"));
+    }
+
+    #[test]
+    fn tags_union_cases() {
+        let json = serde_json::to_string(&Address::String("localhost".to_string())).unwrap();
+        assert_eq!(json, r#"{"1":"localhost"}"#);
+        let port: Address = serde_json::from_str(r#"{"0":80}"#).unwrap();
+        assert!(matches!(port, Address::U16(80)));
+        // Without tags, this would be deserialized as the first case.
+        let path: Address = serde_json::from_str(r#"{"1":"80"}"#).unwrap();
+        assert!(matches!(path, Address::String(path) if path == "80"));
+    }
+
+    #[test]
+    fn rejects_resources() {
+        use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+        use wit_bindgen_core::Files;
+
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(
+                UnresolvedPackage::parse(
+                    "serde.wit".as_ref(),
+                    "
+                        package my:serde
+
+                        interface files {
+                            resource file
+
+                            record entry {
+                                name: string,
+                                file: own<file>,
+                            }
+
+                            entries: func() -> list<entry>
+                        }
+
+                        world server {
+                            import files
+                        }
+                    ",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let opts = wit_bindgen_rust::Opts {
+            serde: true,
+            ..Default::default()
+        };
+        let err = opts
+            .build()
+            .generate(&resolve, world, &mut Files::default())
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("can't serialize `entry`, as it contains resources"),
+            "{err}"
+        );
+    }
+}
+
+mod ownership_conversions {
+    wit_bindgen::generate!({
+        inline: "
+            package my:ownership
+
+            interface files {
+                variant contents {
+                    text(string),
+                    bytes(list<u8>),
+                    empty,
+                }
+
+                record file {
+                    name: string,
+                    tags: option<tuple<string, u32>>,
+                    contents: contents,
+                }
+
+                record directory {
+                    files: list<file>,
+                }
+
+                read: func(name: string) -> file
+                write: func(file: file)
+                listing: func() -> directory
+                sync: func(dir: directory)
+            }
+
+            world forwarder {
+                import files
+            }
+        ",
+        ownership: Borrowing {
+            duplicate_if_necessary: true
+        },
+    });
+
+    use my::ownership::files::{ContentsParam, ContentsResult, FileParam, FileResult};
+
+    #[test]
+    fn converts_between_versions() {
+        let file = FileResult {
+            name: "a.txt".to_string(),
+            tags: Some(("draft".to_string(), 1)),
+            contents: ContentsResult::Bytes(vec![1, 2, 3]),
+        };
+        let param = FileParam::from(&file);
+        assert_eq!(param.name, "a.txt");
+        assert_eq!(param.tags, Some(("draft", 1)));
+        assert!(matches!(param.contents, ContentsParam::Bytes(&[1, 2, 3])));
+
+        let owned = param.to_owned();
+        assert_eq!(owned.name, file.name);
+        assert_eq!(owned.tags, file.tags);
+        assert!(matches!(owned.contents, ContentsResult::Bytes(bytes) if bytes == [1, 2, 3]));
+
+        let text = ContentsParam::Text("hello").to_owned();
+        assert!(matches!(text, ContentsResult::Text(text) if text == "hello"));
+    }
+
+    #[test]
+    fn lists_of_borrowed_types_only_convert_to_owned() {
+        let file = FileParam {
+            name: "b.txt",
+            tags: None,
+            contents: ContentsParam::Empty,
+        };
+        let files = [file];
+        let dir = my::ownership::files::DirectoryParam { files: &files }.to_owned();
+        assert_eq!(dir.files[0].name, "b.txt");
+    }
+}
+
+mod instrument {
+    use std::sync::Mutex;
+    use wit_bindgen::rt::instrument::{set_hook, Call, Direction, Hook};
+
+    wit_bindgen::generate!({
+        inline: "
+            package my:instrument
+
+            world calculator {
+                export add: func(a: u32, b: u32) -> u32
+            }
+        ",
+        export_macro,
+        instrument,
+        export_prefix: "[instrument-hook]",
+    });
+
+    struct Component;
+
+    impl Calculator for Component {
+        fn add(a: u32, b: u32) -> u32 {
+            a + b
+        }
+    }
+
+    export!(Component);
+
+    struct Recorder(Mutex<Vec<(Call, &'static str)>>);
+
+    impl Hook for Recorder {
+        fn enter(&self, call: &Call) -> u64 {
+            self.0.lock().unwrap().push((*call, "enter"));
+            7
+        }
+
+        fn exit(&self, call: &Call, entered: u64) {
+            assert_eq!(entered, 7);
+            self.0.lock().unwrap().push((*call, "exit"));
+        }
+    }
+
+    static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+
+    #[test]
+    fn reports_exports() {
+        set_hook(&RECORDER);
+        assert_eq!(unsafe { _export_add_cabi::<Component>(1, 2) }, 3);
+        let call = Call {
+            interface: None,
+            function: "add",
+            direction: Direction::Export,
+        };
+        assert_eq!(
+            *RECORDER.0.lock().unwrap(),
+            [(call, "enter"), (call, "exit")]
+        );
+    }
+}
+
+mod error_conversion {
+    wit_bindgen::generate!({
+        inline: "
+            package my:errors
+
+            interface parser {
+                enum parse-error {
+                    empty,
+                    invalid,
+                }
+
+                parse: func(input: string) -> result<u32, parse-error>
+                check: func(input: string) -> result<_, string>
+            }
+
+            world checker {
+                export parser
+            }
+        ",
+        exports: {
+            "my:errors/parser": Parser,
+        },
+        error_conversion,
+        dispatch,
+        export_prefix: "[error-conversion-hook]",
+    });
+
+    use exports::my::errors::parser::ParseError;
+    use std::num::{IntErrorKind, ParseIntError};
+
+    pub struct Parser;
+
+    impl From<ParseIntError> for ParseError {
+        fn from(err: ParseIntError) -> ParseError {
+            match err.kind() {
+                IntErrorKind::Empty => ParseError::Empty,
+                _ => ParseError::Invalid,
+            }
+        }
+    }
+
+    impl exports::my::errors::parser::Parser for Parser {
+        type ParseError = ParseIntError;
+        type CheckError = ParseIntError;
+
+        fn parse(input: String) -> Result<u32, ParseIntError> {
+            input.parse()
+        }
+
+        fn check(input: String) -> Result<(), ParseIntError> {
+            input.parse::<u32>().map(drop)
+        }
+    }
+
+    // Results are returned through memory, so the glue converting errors
+    // can't be called off wasm, and only the conversions it uses are checked.
+    #[test]
+    fn converts_errors() {
+        use exports::my::errors::parser::Parser as _;
+
+        let err = Parser::parse(String::new()).map_err(Into::into);
+        assert_eq!(err, Err(ParseError::Empty));
+        let err = Parser::check("x".to_string()).map_err(|e| e.to_string());
+        assert_eq!(err, Err("invalid digit found in string".to_string()));
+    }
+}
+
+mod threads {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+    use wit_bindgen_core::Files;
+
+    #[test]
+    fn rejects_std_feature() {
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(
+                UnresolvedPackage::parse(
+                    "threads.wit".as_ref(),
+                    "
+                        package my:threads
+
+                        world worker {
+                            export run: func() -> string
+                        }
+                    ",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let opts = wit_bindgen_rust::Opts {
+            threads: true,
+            std_feature: true,
+            stubs: true,
+            ..Default::default()
+        };
+        let err = opts
+            .build()
+            .generate(&resolve, world, &mut Files::default())
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("can't be used with `std_feature`"),
+            "{err}"
+        );
+    }
+}
+
+mod multiple_sources {
+    mod paths {
+        wit_bindgen::generate!({
+            path: [
+                "../../tests/codegen/issue569",
+                "../../tests/codegen/issue569/deps/io",
+            ],
+            world: "command",
+        });
+
+        #[test]
+        fn works() {
+            let _ = wasi::io::streams::read;
+        }
+    }
+
+    mod inline_with_deps {
+        wit_bindgen::generate!({
+            inline: "
+                package my:inline
+
+                interface types {
+                    use wasi:io/streams.{input-stream}
+
+                    buffered: func(input: input-stream) -> u32
+                }
+
+                world reader {
+                    import types
+                    import wasi:io/streams
+                }
+            ",
+            path: ["../../tests/codegen/issue569/deps/io"],
+            deps: {
+                "wasi:poll": "../../tests/codegen/issue569/deps/poll",
+            },
+        });
+
+        #[test]
+        fn works() {
+            let _ = my::inline::types::buffered;
+            let _ = wasi::poll::poll::poll_oneoff;
+        }
+    }
+}
+
+mod debug_dir {
+    wit_bindgen::generate!({
+        inline: "
+            package my:debug
+
+            world dumped {
+                export answer: func() -> u32
+            }
+        ",
+        stubs,
+        debug_dir: "../../target/wit-bindgen-debug",
+        export_prefix: "[debug-dir]",
+    });
+
+    #[test]
+    fn writes_expansion() {
+        let dir = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../target/wit-bindgen-debug"
+        );
+        // Dumps are named after the world and a hash of the invocation.
+        let path = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                let name = path.file_name().unwrap().to_str().unwrap();
+                name.starts_with("dumped-") && name.ends_with(".rs")
+            })
+            .unwrap();
+        let src = std::fs::read_to_string(path).unwrap();
+        assert!(src.starts_with("// Generated by `wit-bindgen`"));
+        assert!(src.contains("fn answer()"));
+    }
+}
+
+mod realloc_stats {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use wit_bindgen::rt::realloc::{self, Counts};
+
+    extern "C" {
+        fn cabi_realloc(old_ptr: *mut u8, old_len: usize, align: usize, new_len: usize) -> *mut u8;
+    }
+
+    static CUSTOM_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn custom(old_ptr: *mut u8, old_len: usize, align: usize, new_len: usize) -> *mut u8 {
+        CUSTOM_CALLS.fetch_add(1, Ordering::Relaxed);
+        wit_bindgen::rt::default_realloc(old_ptr, old_len, align, new_len)
+    }
+
+    #[test]
+    fn counts_host_allocations() {
+        realloc::reset_stats();
+        unsafe {
+            let args = cabi_realloc(std::ptr::null_mut(), 0, 1, 16);
+            let args = cabi_realloc(args, 16, 1, 24);
+            std::alloc::dealloc(args, std::alloc::Layout::from_size_align(24, 1).unwrap());
+
+            realloc::set_realloc(custom);
+            let results = {
+                let _results = realloc::import_results();
+                cabi_realloc(std::ptr::null_mut(), 0, 8, 32)
+            };
+            std::alloc::dealloc(results, std::alloc::Layout::from_size_align(32, 8).unwrap());
+        }
+        assert_eq!(CUSTOM_CALLS.load(Ordering::Relaxed), 1);
+        let stats = realloc::stats();
+        assert_eq!(
+            stats.arguments,
+            Counts {
+                allocations: 2,
+                bytes: 24
+            }
+        );
+        assert_eq!(
+            stats.results,
+            Counts {
+                allocations: 1,
+                bytes: 32
+            }
+        );
+    }
+}
+
+mod singleton {
+    wit_bindgen::generate!({
+        inline: "
+            package my:singleton
+
+            world counter {
+                export increment: func(by: u32) -> u32
+                export total: func() -> u32
+            }
+        ",
+        exports: {
+            world: Tally,
+        },
+        singleton,
+        export_prefix: "[singleton-instance]",
+    });
+
+    #[derive(Default)]
+    pub struct Tally {
+        total: u32,
+    }
+
+    impl Counter for Tally {
+        fn increment(&mut self, by: u32) -> u32 {
+            self.total += by;
+            self.total
+        }
+
+        fn total(&mut self) -> u32 {
+            self.total
+        }
+    }
+
+    extern "C" {
+        #[link_name = "[singleton-instance]increment"]
+        fn increment(by: i32) -> i32;
+        #[link_name = "[singleton-instance]total"]
+        fn total() -> i32;
+    }
+
+    #[test]
+    fn keeps_state_across_calls() {
+        unsafe {
+            assert_eq!(total(), 0);
+            assert_eq!(increment(2), 2);
+            assert_eq!(increment(3), 5);
+            assert_eq!(total(), 5);
+        }
+    }
+
+    mod shared {
+        wit_bindgen::generate!({
+            inline: "
+                package my:singleton
+
+                interface adder {
+                    add: func(by: u32) -> u32
+                }
+
+                world shared-counter {
+                    export adder
+                    export total: func() -> u32
+                }
+            ",
+            exports: {
+                world: Tally,
+                "my:singleton/adder": Tally,
+            },
+            singleton,
+            export_prefix: "[singleton-shared]",
+        });
+
+        #[derive(Default)]
+        pub struct Tally {
+            total: u32,
+        }
+
+        impl SharedCounter for Tally {
+            fn total(&mut self) -> u32 {
+                self.total
+            }
+        }
+
+        impl exports::my::singleton::adder::Adder for Tally {
+            fn add(&mut self, by: u32) -> u32 {
+                self.total += by;
+                self.total
+            }
+        }
+
+        extern "C" {
+            #[link_name = "[singleton-shared]my:singleton/adder#add"]
+            fn add(by: i32) -> i32;
+            #[link_name = "[singleton-shared]total"]
+            fn total() -> i32;
+        }
+
+        #[test]
+        fn shares_one_instance_per_type() {
+            unsafe {
+                assert_eq!(add(4), 4);
+                assert_eq!(total(), 4);
+                assert_eq!(add(1), 5);
+                assert_eq!(total(), 5);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_threads() {
+        use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+        use wit_bindgen_core::Files;
+
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(
+                UnresolvedPackage::parse(
+                    "singleton.wit".as_ref(),
+                    "
+                        package my:singleton
+
+                        world counter {
+                            export total: func() -> u32
+                        }
+                    ",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let err = wit_bindgen_rust::Opts {
+            singleton: true,
+            threads: true,
+            stubs: true,
+            ..Default::default()
+        }
+        .build()
+        .generate(&resolve, world, &mut Files::default())
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("can't be used with `threads`"),
+            "{err}"
+        );
+    }
+}
+
+mod async_exports {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    wit_bindgen::generate!({
+        inline: "
+            package my:async-exports
+
+            world adder {
+                export add: func(a: u32, b: u32) -> u32
+            }
+        ",
+        exports: {
+            world: Component,
+        },
+        async_exports,
+        export_prefix: "[adder]",
+    });
+
+    /// Pending once, waking itself, and then ready.
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    pub struct Component;
+
+    impl Adder for Component {
+        async fn add(a: u32, b: u32) -> u32 {
+            YieldNow(false).await;
+            a + b
+        }
+    }
+
+    extern "C" {
+        #[link_name = "[adder]add"]
+        fn add(a: i32, b: i32) -> i32;
+    }
+
+    #[test]
+    fn runs_futures_to_completion() {
+        unsafe {
+            assert_eq!(add(2, 3), 5);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "pending with nothing to wake it")]
+    fn panics_when_stuck() {
+        wit_bindgen::rt::executor::block_on(std::future::pending::<()>());
+    }
+
+    #[test]
+    fn rejects_dynamic_exports() {
+        use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+        use wit_bindgen_core::Files;
+
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(
+                UnresolvedPackage::parse(
+                    "async.wit".as_ref(),
+                    "
+                        package my:async-exports
+
+                        world adder {
+                            export add: func(a: u32, b: u32) -> u32
+                        }
+                    ",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let err = wit_bindgen_rust::Opts {
+            async_exports: true,
+            dynamic_exports: true,
+            stubs: true,
+            ..Default::default()
+        }
+        .build()
+        .generate(&resolve, world, &mut Files::default())
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("can't be used with `dynamic_exports`"),
+            "{err}"
+        );
+    }
+}
+
+mod async_types {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+    use wit_bindgen_core::Files;
+
+    fn generate(src: &str) -> anyhow::Result<()> {
+        let mut resolve = Resolve::default();
+        let pkg = resolve.push(UnresolvedPackage::parse("async.wit".as_ref(), src)?)?;
+        let world = resolve.select_world(pkg, None)?;
+        let mut files = Files::default();
+        wit_bindgen_rust::Opts::default()
+            .build()
+            .generate(&resolve, world, &mut files)
+    }
+
+    #[test]
+    fn rejects_futures_and_streams() {
+        let err = generate(
+            "
+                package my:async
+
+                world fetcher {
+                    import fetch: func(url: string) -> future<list<u8>>
+                }
+            ",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("uses a `future` type"), "{err}");
+
+        let err = generate(
+            "
+                package my:async
+
+                interface body {
+                    record chunk {
+                        data: stream<u8, u32>,
+                    }
+                }
+
+                world reader {
+                    export body
+                }
+            ",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("uses a `stream` type"), "{err}");
+    }
+}
+
+mod remap_types {
+    wit_bindgen::generate!({
+        inline: "
+            package my:remap
+
+            interface units {
+                record meters {
+                    value: u32,
+                }
+
+                double: func(m: meters) -> meters
+                pack: func(pair: tuple<u32, u32>) -> u64
+            }
+
+            world converter {
+                export units
+            }
+        ",
+        exports: {
+            "my:remap/units": Units,
+        },
+        remap_types: {
+            "my:remap/units/meters": crate::remap_types::Meters,
+            "tuple<u32, u32>": crate::remap_types::Pair,
+        },
+        export_prefix: "[remap-types]",
+    });
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Meters(u32);
+
+    impl WitRemap<exports::my::remap::units::Meters> for Meters {
+        fn from_wit(wit: exports::my::remap::units::Meters) -> Self {
+            Meters(wit.value)
+        }
+
+        fn to_wit(&self) -> exports::my::remap::units::Meters {
+            exports::my::remap::units::Meters { value: self.0 }
+        }
+    }
+
+    pub struct Pair(u64);
+
+    impl WitRemap<(u32, u32)> for Pair {
+        fn from_wit((hi, lo): (u32, u32)) -> Self {
+            Pair((u64::from(hi) << 32) | u64::from(lo))
+        }
+
+        fn to_wit(&self) -> (u32, u32) {
+            ((self.0 >> 32) as u32, self.0 as u32)
+        }
+    }
+
+    pub struct Units;
+
+    impl exports::my::remap::units::Units for Units {
+        fn double(m: Meters) -> Meters {
+            Meters(m.0 * 2)
+        }
+
+        fn pack(pair: Pair) -> u64 {
+            pair.0
+        }
+    }
+
+    extern "C" {
+        #[link_name = "[remap-types]my:remap/units#double"]
+        fn double(m: i32) -> i32;
+        #[link_name = "[remap-types]my:remap/units#pack"]
+        fn pack(hi: i32, lo: i32) -> i64;
+    }
+
+    #[test]
+    fn converts_at_the_boundary() {
+        unsafe {
+            assert_eq!(double(21), 42);
+            assert_eq!(pack(1, 2), (1 << 32) | 2);
+        }
+    }
+}
+
+mod invalid_remaps {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+    use wit_bindgen_core::Files;
+
+    fn generate(key: &str) -> anyhow::Result<()> {
+        let src = "
+            package my:remap
+
+            interface types {
+                record point {
+                    x: u32,
+                    y: u32,
+                }
+                type location = point
+                type count = u32
+                type bytes = list<u8>
+
+                move: func(p: location, n: count, data: bytes)
+            }
+
+            world mover {
+                import types
+            }
+        ";
+        let mut resolve = Resolve::default();
+        let pkg = resolve.push(UnresolvedPackage::parse("remap.wit".as_ref(), src)?)?;
+        let world = resolve.select_world(pkg, None)?;
+        let mut files = Files::default();
+        let mut opts = wit_bindgen_rust::Opts::default();
+        opts.remap_types
+            .push((key.to_string(), "crate::Remapped".to_string()));
+        opts.build().generate(&resolve, world, &mut files)
+    }
+
+    #[test]
+    fn reports_the_offending_key() {
+        generate("my:remap/types/point").unwrap();
+        generate("my:remap/types/location").unwrap();
+
+        let err = generate("my:remap/types/count").unwrap_err();
+        let err = err.downcast::<wit_bindgen_rust::InvalidRemap>().unwrap();
+        assert_eq!(err.key, "my:remap/types/count");
+        assert!(err.to_string().contains("an alias of"), "{err}");
+
+        let err = generate("my:remap/types/bytes").unwrap_err();
+        assert!(
+            err.to_string().contains("remap `list<u8>` instead"),
+            "{err}"
+        );
+
+        let err = generate("my:remap/types/missing").unwrap_err();
+        assert!(err.to_string().contains("isn't defined"), "{err}");
+
+        let err = generate("option<u32>").unwrap_err();
+        assert!(err.to_string().contains("isn't used"), "{err}");
+    }
+
+    #[test]
+    fn remaps_world_types_named_like_builtins() {
+        let src = "
+            package my:remap
+
+            world reporter {
+                record result-code {
+                    code: u32,
+                }
+
+                import report: func(code: result-code)
+            }
+        ";
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(UnresolvedPackage::parse("remap.wit".as_ref(), src).unwrap())
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let mut files = Files::default();
+        let mut opts = wit_bindgen_rust::Opts::default();
+        opts.remap_types
+            .push(("result-code".to_string(), "crate::ResultCode".to_string()));
+        opts.build().generate(&resolve, world, &mut files).unwrap();
+
+        let (_, src) = files.iter().next().unwrap();
+        let src = std::str::from_utf8(src).unwrap();
+        assert!(src.contains("crate::ResultCode"), "{src}");
+    }
+}
+
+mod case_conversions {
+    wit_bindgen::generate!({
+        inline: "
+            package my:cases
+
+            interface types {
+                enum color {
+                    dark-red,
+                    green,
+                    blue,
+                }
+
+                flags access {
+                    read,
+                    write,
+                    set-owner,
+                }
+
+                enum errno {
+                    not-found,
+                    denied,
+                }
+
+                open: func(path: string) -> result<u32, errno>
+            }
+
+            world config {
+                import types
+            }
+        ",
+        case_conversions,
+    });
+
+    use my::cases::types::{Access, Color, Errno};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn enums() {
+        assert_eq!(Color::try_from(0u8), Ok(Color::DarkRed));
+        assert_eq!(Color::try_from(2u16), Ok(Color::Blue));
+        let err = Color::try_from(3u32).unwrap_err();
+        assert_eq!(err.ty(), "color");
+        assert_eq!(err.found(), "3");
+
+        assert_eq!("dark-red".parse(), Ok(Color::DarkRed));
+        assert_eq!(Color::Green.to_string(), "green");
+        let err = "purple".parse::<Color>().unwrap_err();
+        assert_eq!(err.to_string(), "`purple` isn't a case of `color`");
+    }
+
+    #[test]
+    fn error_enums() {
+        assert_eq!(Errno::NotFound.to_string(), "not-found (error 0)");
+        assert!(Errno::NotFound.to_string().parse::<Errno>().is_err());
+        assert_eq!(Errno::NotFound.name().parse(), Ok(Errno::NotFound));
+    }
+
+    #[test]
+    fn flags() {
+        let access = Access::READ | Access::SET_OWNER;
+        assert_eq!(
+            access.wit_names().collect::<Vec<_>>(),
+            ["read", "set-owner"]
+        );
+        assert_eq!(access.to_string(), "read, set-owner");
+        assert_eq!(access.to_string().parse(), Ok(access));
+        assert_eq!("".parse(), Ok(Access::empty()));
+        assert_eq!(Access::from_wit_name("write"), Some(Access::WRITE));
+        assert_eq!(Access::from_wit_name("execute"), None);
+        assert_eq!(
+            "read,execute".parse::<Access>().unwrap_err().found(),
+            "execute"
+        );
+    }
+}
+
+mod record_builders {
+    wit_bindgen::generate!({
+        inline: "
+            package my:builders
+
+            interface types {
+                record retry {
+                    attempts: u32,
+                    backoff-ms: option<u64>,
+                }
+
+                record request {
+                    url: string,
+                    headers: list<tuple<string, string>>,
+                    timeout-ms: option<u32>,
+                    retry: retry,
+                    build: bool,
+                }
+
+                record labeled {
+                    label: char,
+                }
+
+                send: func(request: request, labeled: labeled)
+            }
+
+            world client {
+                import types
+            }
+        ",
+        record_builders,
+    });
+
+    use my::builders::types::{Request, Retry};
+
+    #[test]
+    fn builds_from_defaults() {
+        let request = Request::default();
+        assert_eq!(request.url, "");
+        assert!(request.headers.is_empty());
+        assert_eq!(request.timeout_ms, None);
+        assert_eq!(request.retry.attempts, 0);
+
+        let request = Request::builder()
+            .url("https://example.com".to_string())
+            .timeout_ms(30)
+            .retry(Retry::builder().attempts(3).backoff_ms(100).build())
+            .build_(true)
+            .build();
+        assert_eq!(request.url, "https://example.com");
+        assert_eq!(request.timeout_ms, Some(30));
+        assert_eq!(request.retry.attempts, 3);
+        assert_eq!(request.retry.backoff_ms, Some(100));
+        assert!(request.build);
+    }
+
+    mod resources {
+        wit_bindgen::generate!({
+            inline: "
+                package my:builders
+
+                interface handles {
+                    resource r
+
+                    record holder {
+                        x: option<own<r>>,
+                        n: u32,
+                    }
+
+                    hold: func(holder: holder)
+                }
+
+                world holder {
+                    import handles
+                }
+            ",
+            record_builders,
+        });
+
+        use my::builders::handles::Holder;
+
+        #[test]
+        fn builds_records_holding_resources() {
+            let holder = Holder::builder().n(3).build();
+            assert!(holder.x.is_none());
+            assert_eq!(holder.n, 3);
+        }
+    }
+}
+
+mod outline_helpers {
+    use wit_bindgen_core::wit_parser::{Resolve, UnresolvedPackage};
+    use wit_bindgen_core::Files;
+
+    wit_bindgen::generate!({
+        inline: "
+            package my:outline
+
+            world geometry {
+                record point {
+                    x: u32,
+                    y: u32,
+                }
+
+                export dot: func(a: point, b: point) -> u32
+                export scale: func(p: point, by: u32) -> u32
+            }
+        ",
+        exports: {
+            world: Shapes,
+        },
+        outline_helpers,
+        export_prefix: "[outline-helpers]",
+    });
+
+    pub struct Shapes;
+
+    impl Geometry for Shapes {
+        fn dot(a: Point, b: Point) -> u32 {
+            a.x * b.x + a.y * b.y
+        }
+
+        fn scale(p: Point, by: u32) -> u32 {
+            (p.x + p.y) * by
+        }
+    }
+
+    extern "C" {
+        #[link_name = "[outline-helpers]dot"]
+        fn dot(ax: i32, ay: i32, bx: i32, by: i32) -> i32;
+        #[link_name = "[outline-helpers]scale"]
+        fn scale(x: i32, y: i32, by: i32) -> i32;
+    }
+
+    #[test]
+    fn lifts_through_helpers() {
+        unsafe {
+            assert_eq!(dot(1, 2, 3, 4), 11);
+            assert_eq!(scale(1, 2, 3), 9);
+        }
+    }
+
+    #[test]
+    fn emits_each_helper_once() {
+        let mut resolve = Resolve::default();
+        let pkg = resolve
+            .push(
+                UnresolvedPackage::parse(
+                    "outline.wit".as_ref(),
+                    "
+                        package my:outline
+
+                        interface shapes {
+                            record point {
+                                x: u32,
+                                y: u32,
+                            }
+
+                            record named {
+                                name: string,
+                                at: point,
+                            }
+
+                            move: func(p: point, to: point) -> point
+                            rename: func(n: named) -> named
+                            trace: func(path: list<named>) -> list<named>
+                        }
+
+                        world shapes-world {
+                            import shapes
+                            export shapes
+                        }
+                    ",
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        let world = resolve.select_world(pkg, None).unwrap();
+        let mut files = Files::default();
+        let mut opts = wit_bindgen_rust::Opts {
+            outline_helpers: true,
+            ..Default::default()
+        };
+        opts.exports.insert(
+            wit_bindgen_rust::ExportKey::Name("my:outline/shapes".to_string()),
+            "Shapes".to_string(),
+        );
+        opts.build().generate(&resolve, world, &mut files).unwrap();
+        let (_, src) = files.iter().next().unwrap();
+        let src = std::str::from_utf8(src).unwrap();
+
+        let count = |needle: &str| src.matches(needle).count();
+        // Helpers are emitted once per module, however many uses they have:
+        // the import module lowers points passed to `move` and lifts named
+        // values returned by `rename` and `trace`, while the export module
+        // lifts them from both `rename`'s arguments and `trace`'s list.
+        for (helper, modules) in [
+            ("__lower_flat_point", 1),
+            ("__lift_flat_point", 1),
+            ("__lift_named", 2),
+            ("__lower_named", 1),
+        ] {
+            assert_eq!(
+                count(&format!("fn {helper}(")),
+                modules,
+                "{helper} in\n{src}"
+            );
+        }
+        assert_eq!(count("= __lower_flat_point(&"), 2, "{src}");
+        assert_eq!(count("= __lift_flat_point(arg"), 2, "{src}");
+        assert_eq!(count("push(__lift_named(base))"), 2, "{src}");
+    }
+}
@@ -23,6 +23,19 @@ mod codegen_tests {
 
                 #[test]
                 fn works() {}
+
+                mod case_conversions {
+                    wit_bindgen::generate!({
+                        path: $test,
+                        std_feature,
+                        stubs,
+                        case_conversions,
+                        export_prefix: "[case-conversions]",
+                    });
+
+                    #[test]
+                    fn works() {}
+                }
             }
 
         };
@@ -0,0 +1,25 @@
+//! Panic reporting installs a process-wide panic hook, so it's tested in its
+//! own binary rather than alongside tests which panic in parallel.
+
+use std::panic::Location;
+use std::sync::Mutex;
+
+static REPORTS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+fn record(message: &str, location: Option<&Location<'_>>) {
+    let file = location.map(|l| l.file().to_string()).unwrap_or_default();
+    REPORTS.lock().unwrap().push((message.to_string(), file));
+}
+
+#[test]
+fn reports_panics() {
+    let previous = std::panic::take_hook();
+    wit_bindgen::rt::panic::install(record);
+    let result = std::panic::catch_unwind(|| panic!("reported {}", 42));
+    std::panic::set_hook(previous);
+    assert!(result.is_err());
+    assert_eq!(
+        *REPORTS.lock().unwrap(),
+        [("reported 42".to_string(), file!().to_string())]
+    );
+}